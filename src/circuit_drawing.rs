@@ -0,0 +1,255 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+// ASCII and SVG diagrams of QrackCircuit instructions.
+//
+// Every payload of a uniformly controlled gate is drawn as its own gate, with
+// filled controls where its permutation requires `|1>` and open controls
+// where it requires `|0>`. Qrack records swaps as three CNOTs, (with extra
+// controls on the middle one, for controlled swaps), and those are drawn as
+// swaps again.
+
+use std::fmt::Write;
+
+use circuit_instruction::CircuitInstruction;
+use gate_matrix;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    Gate { target: u64, label: String },
+    Swap(u64, u64)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    // One drawn gate
+    //
+    // Attributes:
+    //     controls(Vec<(u64, bool)>): (qubit, required state) pairs
+    //     kind(Kind): what is applied to the targets
+    controls: Vec<(u64, bool)>,
+    kind: Kind
+}
+
+impl Element {
+    fn span(&self) -> (u64, u64) {
+        let targets = match self.kind {
+            Kind::Gate{ target, .. } => [target, target],
+            Kind::Swap(q1, q2) => [q1, q2]
+        };
+        let all = || self.controls.iter().map(|c| c.0).chain(targets.iter().cloned());
+        (all().min().unwrap(), all().max().unwrap())
+    }
+
+    fn label(&self) -> Option<&str> {
+        match self.kind {
+            Kind::Gate{ ref label, .. } => Some(label),
+            Kind::Swap(..) => None
+        }
+    }
+}
+
+struct Payload<'a> {
+    controls: Vec<(u64, bool)>,
+    target: u64,
+    m: &'a [f64;8]
+}
+
+impl<'a> Payload<'a> {
+    fn is_x(&self) -> bool {
+        gate_matrix::approx_eq(self.m, &gate_matrix::X, gate_matrix::TOLERANCE)
+    }
+}
+
+fn elements(instructions: &[CircuitInstruction]) -> Vec<Element> {
+    let payloads: Vec<Payload> = instructions.iter()
+        .flat_map(|i| i.payloads.iter().map(move |(p, m)| Payload{
+            controls: i.controls.iter().enumerate().map(|(j, c)| (*c, (p >> j) & 1 == 1)).collect(),
+            target: i.target,
+            m
+        }))
+        .collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < payloads.len() {
+        if let Some(e) = payloads.get(i..(i + 3)).and_then(swap) {
+            out.push(e);
+            i += 3;
+            continue;
+        }
+        let p = &payloads[i];
+        out.push(Element{ controls: p.controls.clone(), kind: Kind::Gate{ target: p.target, label: gate_matrix::label(p.m) } });
+        i += 1;
+    }
+    out
+}
+
+fn swap(w: &[Payload]) -> Option<Element> {
+    // `CX(b->a) C[c..,a]X(->b) CX(b->a)` is a swap of "a" and "b", controlled by "c.."
+    let (outer, middle) = (&w[0], &w[1]);
+    let (a, b) = (outer.target, outer.controls.first()?.0);
+    if outer.controls != [(b, true)] || !outer.is_x() || !middle.is_x() || middle.target != b
+        || w[2].controls != outer.controls || w[2].target != a || !w[2].is_x() || !middle.controls.contains(&(a, true)) {
+        return None;
+    }
+    let controls = middle.controls.iter().filter(|c| c.0 != a).cloned().collect();
+    Some(Element{ controls, kind: Kind::Swap(a.min(b), a.max(b)) })
+}
+
+fn layout(qubits: u64, elements: Vec<Element>) -> Vec<Vec<Element>> {
+    // Greedily pack elements into columns, left to right, so that no two in a
+    // column overlap, (including the wires their connectors cross)
+    let mut next = vec![0usize; qubits as usize];
+    let mut columns: Vec<Vec<Element>> = Vec::new();
+    for e in elements {
+        let (lo, hi) = e.span();
+        let range = (lo as usize)..=(hi as usize);
+        let col = next[range.clone()].iter().cloned().max().unwrap_or(0);
+        if col == columns.len() {
+            columns.push(Vec::new());
+        }
+        columns[col].push(e);
+        for n in &mut next[range] {
+            *n = col + 1;
+        }
+    }
+    columns
+}
+
+fn chunks<F: Fn(&[Element]) -> usize>(columns: &[Vec<Element>], start: usize, max_width: Option<usize>, width: F) -> Vec<&[Vec<Element>]> {
+    // Split columns into rows no wider than "max_width", (at least one column each)
+    let mut out = Vec::new();
+    let (mut begin, mut used) = (0, start);
+    for (i, c) in columns.iter().enumerate() {
+        let w = width(c);
+        if max_width.is_some_and(|m| used + w > m) && i > begin {
+            out.push(&columns[begin..i]);
+            begin = i;
+            used = start;
+        }
+        used += w;
+    }
+    out.push(&columns[begin..]);
+    out
+}
+
+fn ascii_width(column: &[Element]) -> usize {
+    // Cell width, plus one wire character either side
+    column.iter().map(|e| e.label().map_or(1, |l| l.chars().count() + 2)).max().unwrap_or(1) + 2
+}
+
+pub(crate) fn ascii(qubits: u64, instructions: &[CircuitInstruction], max_width: Option<usize>) -> String {
+    if qubits == 0 {
+        return String::new();
+    }
+    let n = qubits as usize;
+    let columns = layout(qubits, elements(instructions));
+    let names: Vec<String> = (0..n).map(|q| format!("q{}: ", q)).collect();
+    let prefix = names.iter().map(|s| s.len()).max().unwrap();
+    let mut blocks = Vec::new();
+    for chunk in chunks(&columns, prefix, max_width, ascii_width) {
+        let mut rows: Vec<String> = (0..(2 * n - 1))
+            .map(|r| if r % 2 == 0 { format!("{:>1$}", names[r / 2], prefix) } else { " ".repeat(prefix) })
+            .collect();
+        for column in chunk {
+            let w = ascii_width(column);
+            let mid = w / 2;
+            let mut cells: Vec<Vec<char>> = (0..(2 * n - 1))
+                .map(|r| vec![if r % 2 == 0 { '-' } else { ' ' }; w])
+                .collect();
+            for e in column {
+                let (lo, hi) = e.span();
+                for cell in &mut cells[(2 * lo as usize)..=(2 * hi as usize)] {
+                    cell[mid] = '|';
+                }
+                for (q, on) in &e.controls {
+                    cells[2 * *q as usize][mid] = if *on { '@' } else { 'o' };
+                }
+                match e.kind {
+                    Kind::Swap(q1, q2) => {
+                        cells[2 * q1 as usize][mid] = 'x';
+                        cells[2 * q2 as usize][mid] = 'x';
+                    },
+                    Kind::Gate{ target, ref label } => {
+                        let text: Vec<char> = format!("[{}]", label).chars().collect();
+                        let start = mid - text.len() / 2;
+                        cells[2 * target as usize][start..(start + text.len())].copy_from_slice(&text);
+                    }
+                }
+            }
+            for (row, cell) in rows.iter_mut().zip(cells) {
+                row.extend(cell);
+            }
+        }
+        let block: Vec<&str> = rows.iter().map(|r| r.trim_end()).collect();
+        blocks.push(block.join("\n"));
+    }
+    blocks.join("\n\n") + "\n"
+}
+
+const SVG_ROW: usize = 40;
+const SVG_MARGIN: usize = 50;
+const SVG_CHAR: usize = 8;
+
+fn svg_width(column: &[Element]) -> usize {
+    column.iter().map(|e| e.label().map_or(0, |l| SVG_CHAR * l.chars().count() + 24)).max().unwrap_or(0).max(SVG_ROW)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub(crate) fn svg(qubits: u64, instructions: &[CircuitInstruction], max_width: Option<usize>) -> String {
+    let n = qubits as usize;
+    let columns = layout(qubits, elements(instructions));
+    let blocks = chunks(&columns, SVG_MARGIN, max_width, svg_width);
+    let block_height = n * SVG_ROW + SVG_ROW / 2;
+    let width = blocks.iter()
+        .map(|b| SVG_MARGIN + b.iter().map(|c| svg_width(c)).sum::<usize>() + SVG_ROW / 2)
+        .max().unwrap_or(SVG_MARGIN);
+    let height = blocks.len() * block_height;
+    let mut out = String::new();
+    let _ = writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">", width, height);
+    let _ = writeln!(out, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>");
+    let _ = writeln!(out, "<g stroke=\"black\" font-family=\"monospace\" font-size=\"13\" text-anchor=\"middle\" dominant-baseline=\"central\">");
+    for (b, block) in blocks.iter().enumerate() {
+        let y = |q: u64| b * block_height + SVG_ROW / 2 + q as usize * SVG_ROW;
+        let end = SVG_MARGIN + block.iter().map(|c| svg_width(c)).sum::<usize>() + SVG_ROW / 2;
+        for q in 0..qubits {
+            let _ = writeln!(out, "<text x=\"{}\" y=\"{}\" stroke=\"none\">q{}</text>", SVG_MARGIN / 2, y(q), q);
+            let _ = writeln!(out, "<line x1=\"{}\" y1=\"{2}\" x2=\"{}\" y2=\"{2}\"/>", SVG_MARGIN, end, y(q));
+        }
+        let mut left = SVG_MARGIN;
+        for column in block.iter() {
+            let w = svg_width(column);
+            let x = left + w / 2;
+            for e in column {
+                let (lo, hi) = e.span();
+                if lo < hi {
+                    let _ = writeln!(out, "<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\"/>", x, y(lo), y(hi));
+                }
+                for (q, on) in &e.controls {
+                    let _ = writeln!(out, "<circle cx=\"{}\" cy=\"{}\" r=\"5\" fill=\"{}\"/>", x, y(*q), if *on { "black" } else { "white" });
+                }
+                match e.kind {
+                    Kind::Swap(q1, q2) => {
+                        for q in [q1, q2].iter() {
+                            let (x0, x1, y0, y1) = (x - 6, x + 6, y(*q) - 6, y(*q) + 6);
+                            let _ = writeln!(out, "<path d=\"M{} {} L{} {} M{} {} L{} {}\"/>", x0, y0, x1, y1, x0, y1, x1, y0);
+                        }
+                    },
+                    Kind::Gate{ target, ref label } => {
+                        let bw = SVG_CHAR * label.chars().count() + 16;
+                        let _ = writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"26\" fill=\"white\"/>", x - bw / 2, y(target) - 13, bw);
+                        let _ = writeln!(out, "<text x=\"{}\" y=\"{}\" stroke=\"none\">{}</text>", x, y(target), escape(label));
+                    }
+                }
+            }
+            left += w;
+        }
+    }
+    out += "</g>\n</svg>\n";
+    out
+}
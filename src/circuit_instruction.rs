@@ -0,0 +1,189 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+// Rust-side mirror of the gates a QrackCircuit holds.
+//
+// A QrackCircuit with collapse on fuses and reorders gates as they are
+// appended, so instructions describe the circuit as
+// `QrackCircuit::out_to_file()` writes it, not necessarily as it was built.
+
+use std::collections::BTreeMap;
+
+use gate_matrix;
+use qrack_error::QrackError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitInstruction {
+    // Uniformly controlled single-qubit gate
+    //
+    // Attributes:
+    //     target(u64): target qubit
+    //     controls(Vec<u64>): control qubits, ascending
+    //     payloads(BTreeMap<u64, [f64;8]>): matrix per control permutation;
+    //         bit "i" of a permutation is the state of "controls[i]", and
+    //         absent permutations act as identity
+    pub target: u64,
+    pub controls: Vec<u64>,
+    pub payloads: BTreeMap<u64, [f64;8]>
+}
+
+impl CircuitInstruction {
+    pub fn new(m: &[f64;8], q: u64) -> Self {
+        // Uncontrolled gate
+        //
+        // Args:
+        //     m(&[f64;8]): row-major complex list representing the operator.
+        //     q(u64): target qubit
+        let mut payloads = BTreeMap::new();
+        payloads.insert(0, *m);
+        Self{ target: q, controls: Vec::new(), payloads }
+    }
+
+    pub fn controlled(c: &[u64], m: &[f64;8], q: u64, p: u64) -> Result<Self, QrackError> {
+        // Gate applied if the controls match a permutation, as `QrackCircuit::ucmtrx()`
+        //
+        // Args:
+        //     c(&[u64]): control qubits, in any order
+        //     m(&[f64;8]): row-major complex list representing the operator.
+        //     q(u64): target qubit
+        //     p(u64): permutation, with bit "i" the state of "c[i]"
+        //
+        // Raises:
+        //     InvalidArgument: controls repeat or contain the target, there
+        //         are 64 controls or more, or "p" has bits past the controls.
+        if c.len() >= 64 {
+            return Err(QrackError::InvalidArgument(format!("{} controls can't be addressed by a permutation", c.len())));
+        }
+        let mut order: Vec<usize> = (0..c.len()).collect();
+        order.sort_by_key(|i| c[*i]);
+        let controls: Vec<u64> = order.iter().map(|i| c[*i]).collect();
+        if controls.windows(2).any(|w| w[0] == w[1]) || controls.contains(&q) {
+            return Err(QrackError::InvalidArgument(format!("invalid controls {:?} on target {}", c, q)));
+        }
+        if p >> c.len() != 0 {
+            return Err(QrackError::InvalidArgument(format!("permutation {} has bits past {} control(s)", p, c.len())));
+        }
+        let perm = order.iter().enumerate().fold(0u64, |a, (j, i)| a | (((p >> i) & 1) << j));
+        let mut payloads = BTreeMap::new();
+        payloads.insert(perm, *m);
+        Ok(Self{ target: q, controls, payloads })
+    }
+
+    pub fn qubits(&self) -> Vec<u64> {
+        // Controls, then target
+        let mut q = self.controls.clone();
+        q.push(self.target);
+        q
+    }
+
+    pub fn is_controlled(&self) -> bool {
+        !self.controls.is_empty()
+    }
+
+    pub fn permutation(&self) -> Option<(u64, &[f64;8])> {
+        // The (permutation, matrix) pair, if there is exactly one payload
+        if self.payloads.len() != 1 {
+            return None;
+        }
+        self.payloads.iter().next().map(|(p, m)| (*p, m))
+    }
+
+    pub fn remapped<F: Fn(u64) -> u64>(&self, f: F) -> Result<Self, QrackError> {
+        // Same gate, with every qubit "q" moved to "f(q)"
+        //
+        // Raises:
+        //     InvalidArgument: "f" sends two of the gate's qubits to one.
+        let c: Vec<u64> = self.controls.iter().map(|q| f(*q)).collect();
+        self.rebuild(&c, f(self.target), 0)
+    }
+
+    pub fn with_controls(&self, controls: &[u64], anti_controls: &[u64]) -> Result<Self, QrackError> {
+        // Same gate, applied only if every qubit of "controls" is `|1>` and
+        // every qubit of "anti_controls" is `|0>`
+        //
+        // Raises:
+        //     InvalidArgument: an added control repeats or is one of the
+        //         gate's qubits, or there would be 64 controls or more.
+        let c: Vec<u64> = self.controls.iter().chain(controls).chain(anti_controls).cloned().collect();
+        let extra = (self.controls.len()..(self.controls.len() + controls.len()))
+            .fold(0u64, |a, j| a | 1u64.checked_shl(j as u32).unwrap_or(0));
+        self.rebuild(&c, self.target, extra)
+    }
+
+    fn rebuild(&self, c: &[u64], q: u64, extra: u64) -> Result<Self, QrackError> {
+        // Gate with controls "c", (the current ones first), and target "q",
+        // with permutation bits "extra" set for the added controls
+        let mut out = Self::controlled(c, &gate_matrix::I, q, 0)?;
+        out.payloads.clear();
+        for (p, m) in &self.payloads {
+            out.payloads.extend(Self::controlled(c, m, q, p | extra)?.payloads);
+        }
+        Ok(out)
+    }
+}
+
+pub(crate) fn parse_circuit(text: &str) -> Result<(u64, Vec<CircuitInstruction>), QrackError> {
+    // Parse Qrack's circuit serialization, (whitespace-separated):
+    //     qubits gate_count gate*
+    //     gate := target control_count control* payload_count payload*
+    //     payload := permutation "(re,im)" "(re,im)" "(re,im)" "(re,im)"
+    //
+    // Returns:
+    //     qubit count, and instructions in application order
+    let mut tokens = text.split_whitespace();
+    let mut next = |what: &str| tokens.next()
+        .ok_or_else(|| QrackError::InvalidArgument(format!("circuit data ends before {}", what)));
+    fn int(t: &str, what: &str) -> Result<u64, QrackError> {
+        t.parse().map_err(|_| QrackError::InvalidArgument(format!("circuit data has invalid {} \"{}\"", what, t)))
+    }
+    let qubits = int(next("qubit count")?, "qubit count")?;
+    let gate_count = int(next("gate count")?, "gate count")?;
+    let mut gates = Vec::new();
+    for _ in 0..gate_count {
+        let target = int(next("target")?, "target")?;
+        let control_count = int(next("control count")?, "control count")?;
+        if control_count >= 64 {
+            return Err(QrackError::InvalidArgument(format!("circuit data has {} controls on one gate", control_count)));
+        }
+        let mut controls = Vec::new();
+        for _ in 0..control_count {
+            controls.push(int(next("control")?, "control")?);
+        }
+        for q in controls.iter().chain(Some(&target)) {
+            if *q >= qubits {
+                return Err(QrackError::QubitOutOfRange{ qubit: *q, count: qubits });
+            }
+        }
+        if controls.windows(2).any(|w| w[0] >= w[1]) || controls.contains(&target) {
+            return Err(QrackError::InvalidArgument(format!("circuit data has invalid controls {:?} on target {}", controls, target)));
+        }
+        let payload_count = int(next("payload count")?, "payload count")?;
+        let mut payloads = BTreeMap::new();
+        for _ in 0..payload_count {
+            let perm = int(next("permutation")?, "permutation")?;
+            if perm >> control_count != 0 {
+                return Err(QrackError::InvalidArgument(format!("circuit data has permutation {} for {} controls", perm, control_count)));
+            }
+            let mut m = [0.0; 8];
+            for i in 0..4 {
+                let t = next("matrix element")?;
+                let (re, im) = t.strip_prefix('(').and_then(|t| t.strip_suffix(')')).and_then(|t| t.split_once(','))
+                    .ok_or_else(|| QrackError::InvalidArgument(format!("circuit data has invalid matrix element \"{}\"", t)))?;
+                let parse = |v: &str| v.parse::<f64>()
+                    .map_err(|_| QrackError::InvalidArgument(format!("circuit data has invalid matrix element \"{}\"", t)));
+                m[2 * i] = parse(re)?;
+                m[2 * i + 1] = parse(im)?;
+            }
+            if payloads.insert(perm, m).is_some() {
+                return Err(QrackError::InvalidArgument(format!("circuit data repeats permutation {}", perm)));
+            }
+        }
+        gates.push(CircuitInstruction{ target, controls, payloads });
+    }
+    if let Some(t) = tokens.next() {
+        return Err(QrackError::InvalidArgument(format!("circuit data has trailing \"{}\"", t)));
+    }
+    Ok((qubits, gates))
+}
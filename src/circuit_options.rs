@@ -0,0 +1,15 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CircuitOptions {
+    // Construction options of a QrackCircuit, as arguments of
+    // `qrack_system::init_qcircuit()`
+    //
+    // Attributes:
+    //     collapse(bool): let Qrack collapse each appended gate into earlier
+    //         gates it commutes with, (off by default)
+    pub collapse: bool
+}
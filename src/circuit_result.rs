@@ -0,0 +1,66 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::fmt;
+
+use qrack_error::QrackError;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CircuitResult {
+    // Classical register after a `DynamicCircuit::run()`
+    //
+    // Bits that no measurement wrote are `false`.
+    //
+    // Attributes:
+    //     bits(Vec<bool>): classical bits, by index
+    bits: Vec<bool>
+}
+
+impl CircuitResult {
+    pub fn new(bits: Vec<bool>) -> Self {
+        Self{ bits }
+    }
+
+    pub fn bits(&self) -> &[bool] {
+        &self.bits
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_empty()
+    }
+
+    pub fn bit(&self, clbit: u64) -> Result<bool, QrackError> {
+        // Raises:
+        //     QubitOutOfRange: "clbit" is past the register.
+        self.bits.get(clbit as usize).cloned()
+            .ok_or(QrackError::QubitOutOfRange{ qubit: clbit, count: self.bits.len() as u64 })
+    }
+
+    pub fn value(&self, clbits: &[u64]) -> Result<u64, QrackError> {
+        // Integer with bit "i" read from "clbits[i]"
+        //
+        // Raises:
+        //     QubitOutOfRange: a bit is past the register.
+        //     InvalidArgument: more than 64 bits.
+        if clbits.len() > 64 {
+            return Err(QrackError::InvalidArgument(format!("{} bits don't fit in a u64", clbits.len())));
+        }
+        clbits.iter().enumerate().try_fold(0u64, |v, (i, c)| Ok(v | ((self.bit(*c)? as u64) << i)))
+    }
+}
+
+impl fmt::Display for CircuitResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Bit string, highest bit first, (as OpenQASM prints registers)
+        for b in self.bits.iter().rev() {
+            write!(f, "{}", if *b { '1' } else { '0' })?;
+        }
+        Ok(())
+    }
+}
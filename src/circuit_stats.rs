@@ -0,0 +1,92 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::collections::BTreeMap;
+
+use circuit_instruction::CircuitInstruction;
+use gate_matrix;
+
+// Single-qubit gates of the Clifford+T set, (by `gate_matrix::name()`)
+const CLIFFORD_T: [&str; 10] = ["X", "Y", "Z", "H", "S", "Sdg", "T", "Tdg", "SX", "SXdg"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CircuitStats {
+    // Resource metrics of a circuit
+    //
+    // Each payload of a uniformly controlled gate counts as one gate, acting
+    // on all the gate's controls and its target. Identity payloads aren't
+    // counted.
+    //
+    // Attributes:
+    //     qubit_count(u64): circuit width
+    //     gate_count(usize): total gates
+    //     depth(usize): layers, with gates scheduled as early as possible
+    //     qubit_depths(Vec<usize>): layer of the last gate on each qubit
+    //     gate_counts(BTreeMap<String, usize>): count per gate type, named
+    //         from the matrix, with "C" per control, (e.g. "H", "CX", "CCX"),
+    //         or "C{n}" past two controls, (e.g. "C3X")
+    //     controlled_counts(BTreeMap<usize, usize>): count of controlled
+    //         gates per number of controls
+    //     two_qubit_count(usize): gates acting on exactly two qubits
+    //     t_count(usize): uncontrolled T and T-adjoint gates
+    //     is_clifford_t(bool): every gate is a single-qubit Clifford+T gate,
+    //         or a Pauli with one control
+    pub qubit_count: u64,
+    pub gate_count: usize,
+    pub depth: usize,
+    pub qubit_depths: Vec<usize>,
+    pub gate_counts: BTreeMap<String, usize>,
+    pub controlled_counts: BTreeMap<usize, usize>,
+    pub two_qubit_count: usize,
+    pub t_count: usize,
+    pub is_clifford_t: bool
+}
+
+impl CircuitStats {
+    pub fn from_instructions(qubit_count: u64, instructions: &[CircuitInstruction]) -> Self {
+        let mut stats = CircuitStats{ qubit_count, qubit_depths: vec![0; qubit_count as usize], is_clifford_t: true, ..Self::default() };
+        for i in instructions {
+            for m in i.payloads.values() {
+                let name = gate_matrix::name(m);
+                if name == Some("I") {
+                    continue;
+                }
+                let qubits = i.qubits();
+                let layer = 1 + qubits.iter().map(|q| stats.qubit_depths[*q as usize]).max().unwrap_or(0);
+                for q in &qubits {
+                    stats.qubit_depths[*q as usize] = layer;
+                }
+                stats.depth = stats.depth.max(layer);
+                stats.gate_count += 1;
+
+                let controls = i.controls.len();
+                let base = name.map_or_else(|| kind(&gate_matrix::label(m)), |n| n.to_string());
+                let prefix = if controls > 2 { format!("C{}", controls) } else { "C".repeat(controls) };
+                *stats.gate_counts.entry(prefix + &base).or_insert(0) += 1;
+                if controls > 0 {
+                    *stats.controlled_counts.entry(controls).or_insert(0) += 1;
+                }
+                if qubits.len() == 2 {
+                    stats.two_qubit_count += 1;
+                }
+                if controls == 0 && (name == Some("T") || name == Some("Tdg")) {
+                    stats.t_count += 1;
+                }
+                let clifford_t = match (controls, name) {
+                    (0, Some(n)) => CLIFFORD_T.contains(&n),
+                    (1, Some(n)) => n == "X" || n == "Y" || n == "Z",
+                    _ => false
+                };
+                stats.is_clifford_t &= clifford_t;
+            }
+        }
+        stats
+    }
+}
+
+fn kind(label: &str) -> String {
+    // Gate family of a label, without its angles, (e.g. "RZ" of "RZ(0.5)")
+    label.split('(').next().unwrap().to_string()
+}
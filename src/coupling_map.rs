@@ -0,0 +1,326 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
+use std::path::Path;
+
+use qrack_error::QrackError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CouplingMap {
+    // Device connectivity: pairs of physical qubits that a two-qubit gate
+    // may act on, in either direction
+    //
+    // Attributes:
+    //     qubit_count(u64): physical qubits
+    //     edges(BTreeSet<(u64, u64)>): coupled pairs, lower qubit first
+    qubit_count: u64,
+    edges: BTreeSet<(u64, u64)>
+}
+
+impl CouplingMap {
+    pub fn new(qubit_count: u64, edges: &[(u64, u64)]) -> Result<Self, QrackError> {
+        // Coupling map from an edge list
+        //
+        // Args:
+        //     qubit_count(u64): physical qubits
+        //     edges(&[(u64, u64)]): coupled pairs, (direction is ignored)
+        //
+        // Raises:
+        //     QubitOutOfRange: an edge names a qubit past "qubit_count".
+        //     InvalidArgument: an edge couples a qubit to itself.
+        let mut set = BTreeSet::new();
+        for (a, b) in edges {
+            for q in [a, b].iter() {
+                if **q >= qubit_count {
+                    return Err(QrackError::QubitOutOfRange{ qubit: **q, count: qubit_count });
+                }
+            }
+            if a == b {
+                return Err(QrackError::InvalidArgument(format!("edge couples qubit {} to itself", a)));
+            }
+            set.insert((*a.min(b), *a.max(b)));
+        }
+        Ok(CouplingMap{ qubit_count, edges: set })
+    }
+
+    pub fn line(qubit_count: u64) -> Self {
+        // Qubits coupled in a chain, `0-1-2-...`
+        let edges: Vec<(u64, u64)> = (1..qubit_count).map(|q| (q - 1, q)).collect();
+        Self::new(qubit_count, &edges).unwrap()
+    }
+
+    pub fn ring(qubit_count: u64) -> Self {
+        // Chain with the last qubit coupled back to the first
+        let mut edges: Vec<(u64, u64)> = (1..qubit_count).map(|q| (q - 1, q)).collect();
+        if qubit_count > 2 {
+            edges.push((qubit_count - 1, 0));
+        }
+        Self::new(qubit_count, &edges).unwrap()
+    }
+
+    pub fn grid(rows: u64, cols: u64) -> Self {
+        // Nearest-neighbor square lattice, numbered row by row
+        let mut edges = Vec::new();
+        for r in 0..rows {
+            for c in 0..cols {
+                let q = r * cols + c;
+                if c + 1 < cols {
+                    edges.push((q, q + 1));
+                }
+                if r + 1 < rows {
+                    edges.push((q, q + cols));
+                }
+            }
+        }
+        Self::new(rows * cols, &edges).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, QrackError> {
+        // Coupling map from JSON
+        //
+        // Either a bare edge list, (e.g. `[[0, 1], [1, 2]]`, with qubit count
+        // one past the highest qubit), or an object with an "edges" list and
+        // an optional "qubits" count, (other keys are ignored).
+        //
+        // Raises:
+        //     InvalidArgument: the JSON is malformed, or not of either shape.
+        //     QubitOutOfRange: an edge names a qubit past "qubits".
+        let value = Json::parse(json)?;
+        let (count, edges) = match value {
+            Json::Array(_) => (None, &value),
+            Json::Object(ref fields) => {
+                let get = |k: &str| fields.iter().find(|f| f.0 == k).map(|f| &f.1);
+                let count = match get("qubits") {
+                    Some(v) => Some(v.as_u64().ok_or_else(|| shape("\"qubits\" is not a qubit count"))?),
+                    None => None
+                };
+                (count, get("edges").ok_or_else(|| shape("missing \"edges\""))?)
+            },
+            _ => return Err(shape("expected an edge list or an object"))
+        };
+        let edges = match edges {
+            Json::Array(items) => items.iter()
+                .map(|e| match e {
+                    Json::Array(p) if p.len() == 2 => match (p[0].as_u64(), p[1].as_u64()) {
+                        (Some(a), Some(b)) => Ok((a, b)),
+                        _ => Err(shape("edge qubits must be non-negative integers"))
+                    },
+                    _ => Err(shape("each edge must be a pair of qubits"))
+                })
+                .collect::<Result<Vec<(u64, u64)>, QrackError>>()?,
+            _ => return Err(shape("\"edges\" is not a list"))
+        };
+        let count = count.unwrap_or_else(|| edges.iter().map(|e| e.0.max(e.1) + 1).max().unwrap_or(0));
+        Self::new(count, &edges)
+    }
+
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> Result<Self, QrackError> {
+        // Raises:
+        //     Io: the file couldn't be read.
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn to_json(&self) -> String {
+        // Object form, as read by `from_json()`
+        let edges: Vec<String> = self.edges.iter().map(|(a, b)| format!("[{}, {}]", a, b)).collect();
+        format!("{{\"qubits\": {}, \"edges\": [{}]}}", self.qubit_count, edges.join(", "))
+    }
+
+    pub fn qubit_count(&self) -> u64 {
+        self.qubit_count
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.edges.iter().cloned()
+    }
+
+    pub fn is_coupled(&self, q1: u64, q2: u64) -> bool {
+        self.edges.contains(&(q1.min(q2), q1.max(q2)))
+    }
+
+    pub fn neighbors(&self, q: u64) -> Vec<u64> {
+        // Qubits coupled to "q", ascending
+        let mut out: Vec<u64> = self.edges.iter()
+            .filter_map(|&(a, b)| if a == q { Some(b) } else if b == q { Some(a) } else { None })
+            .collect();
+        out.sort_unstable();
+        out
+    }
+
+    pub fn distances(&self) -> Vec<Vec<Option<u64>>> {
+        // Shortest path lengths between every pair of qubits, in edges, (None
+        // if disconnected)
+        let n = self.qubit_count as usize;
+        let adjacency: Vec<Vec<u64>> = (0..self.qubit_count).map(|q| self.neighbors(q)).collect();
+        (0..n).map(|start| {
+            let mut dist = vec![None; n];
+            dist[start] = Some(0);
+            let mut queue = VecDeque::new();
+            queue.push_back(start);
+            while let Some(q) = queue.pop_front() {
+                let d = dist[q].unwrap();
+                for nb in &adjacency[q] {
+                    if dist[*nb as usize].is_none() {
+                        dist[*nb as usize] = Some(d + 1);
+                        queue.push_back(*nb as usize);
+                    }
+                }
+            }
+            dist
+        }).collect()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.qubit_count == 0 || self.distances()[0].iter().all(|d| d.is_some())
+    }
+}
+
+fn shape(message: &str) -> QrackError {
+    QrackError::InvalidArgument(format!("coupling map JSON: {}", message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    // Just enough JSON to read coupling maps, (strings are not unescaped)
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>)
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, QrackError> {
+        let mut p = JsonParser{ text: text.as_bytes(), pos: 0 };
+        let v = p.value()?;
+        p.space();
+        if p.pos != p.text.len() {
+            return Err(p.error("trailing characters"));
+        }
+        Ok(v)
+    }
+
+    fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Json::Number(x) if x >= 0.0 && x.fract() == 0.0 && x < 2f64.powi(53) => Some(x as u64),
+            _ => None
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a [u8],
+    pos: usize
+}
+
+impl<'a> JsonParser<'a> {
+    fn error(&self, message: &str) -> QrackError {
+        shape(&format!("{} at byte {}", message, self.pos))
+    }
+
+    fn space(&mut self) {
+        while self.pos < self.text.len() && (self.text[self.pos] as char).is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.space();
+        if self.text.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, QrackError> {
+        self.space();
+        match self.text.get(self.pos) {
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.error("expected ',' or ']'"));
+                        }
+                    }
+                }
+                Ok(Json::Array(items))
+            },
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.space();
+                        let key = match self.value()? {
+                            Json::String(k) => k,
+                            _ => return Err(self.error("expected a string key"))
+                        };
+                        if !self.eat(b':') {
+                            return Err(self.error("expected ':'"));
+                        }
+                        fields.push((key, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        if !self.eat(b',') {
+                            return Err(self.error("expected ',' or '}'"));
+                        }
+                    }
+                }
+                Ok(Json::Object(fields))
+            },
+            Some(b'"') => {
+                let start = self.pos + 1;
+                let mut end = start;
+                while end < self.text.len() && self.text[end] != b'"' {
+                    end += if self.text[end] == b'\\' { 2 } else { 1 };
+                }
+                if end >= self.text.len() {
+                    return Err(self.error("unterminated string"));
+                }
+                self.pos = end + 1;
+                Ok(Json::String(String::from_utf8_lossy(&self.text[start..end]).into_owned()))
+            },
+            Some(c) if c.is_ascii_alphabetic() => {
+                let start = self.pos;
+                while self.pos < self.text.len() && self.text[self.pos].is_ascii_alphabetic() {
+                    self.pos += 1;
+                }
+                match &self.text[start..self.pos] {
+                    b"null" => Ok(Json::Null),
+                    b"true" => Ok(Json::Bool(true)),
+                    b"false" => Ok(Json::Bool(false)),
+                    _ => {
+                        self.pos = start;
+                        Err(self.error("unexpected word"))
+                    }
+                }
+            },
+            Some(_) => {
+                let start = self.pos;
+                while self.pos < self.text.len() && b"+-.eE0123456789".contains(&self.text[self.pos]) {
+                    self.pos += 1;
+                }
+                let token = String::from_utf8_lossy(&self.text[start..self.pos]).into_owned();
+                token.parse::<f64>().map(Json::Number).map_err(|_| {
+                    self.pos = start;
+                    self.error("expected a value")
+                })
+            },
+            None => Err(self.error("unexpected end of input"))
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use circuit_result::CircuitResult;
+use qrack_circuit::QrackCircuit;
+use qrack_error::QrackError;
+use qrack_simulator::QrackSimulator;
+
+#[derive(Clone)]
+enum Op {
+    // Unitary segment, run with `QrackCircuit::run()`
+    Unitary(QrackCircuit),
+    // Measurement of "qubit" into "clbit", optionally forced to a result
+    Measure { qubit: u64, clbit: u64, forced: Option<bool> },
+    Reset(u64),
+    // Circuit run only if "clbits" read "value", (bit "i" is "clbits[i]")
+    Conditional { clbits: Vec<u64>, value: u64, circuit: QrackCircuit }
+}
+
+#[derive(Clone, Default)]
+pub struct DynamicCircuit {
+    // Circuit with mid-circuit measurement, reset, and gates conditioned on
+    // a classical register
+    //
+    // Unitary gates accumulate in QrackCircuit segments, which run through
+    // Qrack in one call each. Measurements, resets and conditionals sit
+    // between segments, and run on the simulator directly.
+    //
+    // Attributes:
+    //     ops(Vec<Op>): operations, in order
+    //     clbit_count(u64): classical register width
+    ops: Vec<Op>,
+    clbit_count: u64
+}
+
+impl DynamicCircuit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clbit_count(&self) -> u64 {
+        self.clbit_count
+    }
+
+    pub fn set_clbit_count(&mut self, clbit_count: u64) {
+        // Widen the classical register, (it never shrinks below the bits in use)
+        self.clbit_count = self.clbit_count.max(clbit_count);
+    }
+
+    pub fn gates(&mut self) -> &QrackCircuit {
+        // Current unitary segment, to append gates to
+        //
+        // A new segment starts after every non-unitary operation.
+        if !matches!(self.ops.last(), Some(Op::Unitary(_))) {
+            self.ops.push(Op::Unitary(QrackCircuit::new()));
+        }
+        match self.ops.last() {
+            Some(Op::Unitary(c)) => c,
+            _ => unreachable!()
+        }
+    }
+
+    pub fn append(&mut self, circuit: &QrackCircuit) {
+        // Append a copy of a unitary circuit, as its own segment
+        self.ops.push(Op::Unitary(circuit.clone()));
+    }
+
+    pub fn measure(&mut self, qubit: u64, clbit: u64) {
+        // Measure "qubit" in the Z basis, into "clbit", with `QrackSimulator::m()`
+        self.set_clbit_count(clbit + 1);
+        self.ops.push(Op::Measure{ qubit, clbit, forced: None });
+    }
+
+    pub fn force_measure(&mut self, qubit: u64, clbit: u64, result: bool) {
+        // Measure "qubit" into "clbit", forcing "result", with `QrackSimulator::force_m()`
+        self.set_clbit_count(clbit + 1);
+        self.ops.push(Op::Measure{ qubit, clbit, forced: Some(result) });
+    }
+
+    pub fn reset(&mut self, qubit: u64) {
+        // Measure "qubit", and flip it back to `|0>` if it was `|1>`
+        self.ops.push(Op::Reset(qubit));
+    }
+
+    pub fn conditional(&mut self, clbits: Vec<u64>, value: u64, circuit: &QrackCircuit) -> Result<(), QrackError> {
+        // Run a copy of "circuit" only if "clbits" read "value"
+        //
+        // Args:
+        //     clbits(Vec<u64>): classical bits to read
+        //     value(u64): required value, with bit "i" for "clbits[i]"
+        //     circuit(&QrackCircuit): gates to run
+        //
+        // Raises:
+        //     InvalidArgument: more than 64 bits, or "value" has bits past them.
+        if clbits.len() > 64 || (clbits.len() < 64 && value >> clbits.len() != 0) {
+            return Err(QrackError::InvalidArgument(format!("value {} doesn't fit {} bit(s)", value, clbits.len())));
+        }
+        if let Some(c) = clbits.iter().max() {
+            self.set_clbit_count(c + 1);
+        }
+        self.ops.push(Op::Conditional{ clbits, value, circuit: circuit.clone() });
+        Ok(())
+    }
+
+    pub fn run(&self, qsim: &QrackSimulator) -> Result<CircuitResult, QrackError> {
+        // Run on a simulator, filling a classical register
+        //
+        // Raises:
+        //     Native: Qrack reported an error code.
+        //
+        // Returns:
+        //     classical register, with `clbit_count()` bits
+        let mut bits = vec![false; self.clbit_count as usize];
+        for op in &self.ops {
+            match op {
+                Op::Unitary(c) => c.run(qsim)?,
+                Op::Measure{ qubit, clbit, forced } => {
+                    bits[*clbit as usize] = match forced {
+                        Some(r) => qsim.force_m(*qubit, *r)?,
+                        None => qsim.m(*qubit)?
+                    } != 0;
+                },
+                Op::Reset(qubit) => {
+                    if qsim.m(*qubit)? != 0 {
+                        qsim.x(*qubit)?;
+                    }
+                },
+                Op::Conditional{ clbits, value, circuit } => {
+                    let v = clbits.iter().enumerate().fold(0u64, |v, (i, c)| v | ((bits[*c as usize] as u64) << i));
+                    if v == *value {
+                        circuit.run(qsim)?;
+                    }
+                }
+            }
+        }
+        Ok(CircuitResult::new(bits))
+    }
+}
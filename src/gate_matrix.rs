@@ -0,0 +1,153 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+// 2x2 operator matrices of the standard gates, as used by QrackCircuit.
+//
+// Each matrix is an array of 8 (real) floating point numbers, grouped in
+// immediate pairs of real then imaginary components of complex numbers, then
+// in row-major order.
+
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+use pauli::Pauli;
+
+pub const I: [f64;8] = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+pub const X: [f64;8] = [0.0, 0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0];
+pub const Y: [f64;8] = [0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 0.0, 0.0];
+pub const Z: [f64;8] = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0];
+pub const H: [f64;8] = [FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2, 0.0, -FRAC_1_SQRT_2, 0.0];
+pub const S: [f64;8] = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+pub const ADJ_S: [f64;8] = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0];
+pub const T: [f64;8] = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, FRAC_1_SQRT_2, FRAC_1_SQRT_2];
+pub const ADJ_T: [f64;8] = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2];
+pub const SX: [f64;8] = [0.5, 0.5, 0.5, -0.5, 0.5, -0.5, 0.5, 0.5];
+pub const SXDG: [f64;8] = [0.5, -0.5, 0.5, 0.5, 0.5, 0.5, 0.5, -0.5];
+
+// Tolerance of matrix recognition; Qrack serializes single precision
+// matrices with 6 significant digits.
+pub const TOLERANCE: f64 = 1e-5;
+
+pub fn u(th: f64, ph: f64, la: f64) -> [f64;8] {
+    // General unitary gate
+    //
+    // `U(theta, phi, lambda) = RZ(phi + pi/2)RX(theta)RZ(lambda - pi/2)`,
+    // (up to global phase, with `U(0, 0, 0) = I`)
+    let (c, s) = ((th / 2.0).cos(), (th / 2.0).sin());
+    [
+        c, 0.0,
+        -la.cos() * s, -la.sin() * s,
+        ph.cos() * s, ph.sin() * s,
+        (ph + la).cos() * c, (ph + la).sin() * c
+    ]
+}
+
+pub fn r(b: Pauli, ph: f64) -> [f64;8] {
+    // Rotation gate
+    //
+    // `R(b, phi) = e^{-i*phi/2*b}`, (as Q# defines "R()", so the identity
+    // basis contributes a global phase)
+    let (c, s) = ((ph / 2.0).cos(), (ph / 2.0).sin());
+    match b {
+        Pauli::PauliI => [c, -s, 0.0, 0.0, 0.0, 0.0, c, -s],
+        Pauli::PauliX => [c, 0.0, 0.0, -s, 0.0, -s, c, 0.0],
+        Pauli::PauliY => [c, 0.0, -s, 0.0, s, 0.0, c, 0.0],
+        Pauli::PauliZ => [c, -s, 0.0, 0.0, 0.0, 0.0, c, s]
+    }
+}
+
+pub fn phase(la: f64) -> [f64;8] {
+    // Phase gate, `diag(1, e^{i*lambda})`
+    [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, la.cos(), la.sin()]
+}
+
+pub fn mul(a: &[f64;8], b: &[f64;8]) -> [f64;8] {
+    // Matrix product `a*b`, (apply "b", then "a")
+    let mut out = [0.0; 8];
+    for r in 0..2 {
+        for c in 0..2 {
+            for k in 0..2 {
+                let (x, y) = ((2 * r + k) * 2, (2 * k + c) * 2);
+                out[(2 * r + c) * 2] += a[x] * b[y] - a[x + 1] * b[y + 1];
+                out[(2 * r + c) * 2 + 1] += a[x] * b[y + 1] + a[x + 1] * b[y];
+            }
+        }
+    }
+    out
+}
+
+pub fn approx_eq(a: &[f64;8], b: &[f64;8], tol: f64) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() <= tol)
+}
+
+pub fn name(m: &[f64;8]) -> Option<&'static str> {
+    // Name of a fixed standard gate, (exactly, not up to global phase)
+    const NAMED: [(&str, [f64;8]); 11] = [
+        ("I", I), ("X", X), ("Y", Y), ("Z", Z), ("H", H), ("S", S), ("Sdg", ADJ_S),
+        ("T", T), ("Tdg", ADJ_T), ("SX", SX), ("SXdg", SXDG)
+    ];
+    NAMED.iter().find(|n| approx_eq(&n.1, m, TOLERANCE)).map(|n| n.0)
+}
+
+pub fn label(m: &[f64;8]) -> String {
+    // Short display name: a standard gate name, else "RX", "RY", "RZ" or "P"
+    // with its angle, else "U" with Euler angles, (dropping global phase)
+    if let Some(n) = name(m) {
+        return n.to_string();
+    }
+    let candidates = [
+        ("RX", 2.0 * (-m[3]).atan2(m[0]), Some(Pauli::PauliX)),
+        ("RY", 2.0 * m[4].atan2(m[0]), Some(Pauli::PauliY)),
+        ("RZ", 2.0 * (-m[1]).atan2(m[0]), Some(Pauli::PauliZ)),
+        ("P", m[7].atan2(m[6]), None)
+    ];
+    for (n, th, b) in candidates.iter() {
+        let g = match b {
+            Some(b) => r(*b, *th),
+            None => phase(*th)
+        };
+        if approx_eq(&g, m, TOLERANCE) {
+            return format!("{}({})", n, angle(*th));
+        }
+    }
+    let (th, ph, la, _) = zyz(m);
+    format!("U({},{},{})", angle(th), angle(ph), angle(la))
+}
+
+fn angle(x: f64) -> String {
+    let s = format!("{:.3}", x);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
+
+pub fn zyz(m: &[f64;8]) -> (f64, f64, f64, f64) {
+    // Decompose a 2x2 unitary as `e^{i*gamma} U(theta, phi, lambda)`
+    //
+    // Returns:
+    //     (theta, phi, lambda, gamma), with angles in (-pi, pi]
+    const EPSILON: f64 = 1e-12;
+    let arg = |re: f64, im: f64| im.atan2(re);
+    let (a, b, c, d) = ((m[0], m[1]), (m[2], m[3]), (m[4], m[5]), (m[6], m[7]));
+    let norm = |z: (f64, f64)| z.0.hypot(z.1);
+    let cos = ((norm(a).powi(2) + norm(d).powi(2)) / 2.0).sqrt();
+    let sin = ((norm(b).powi(2) + norm(c).powi(2)) / 2.0).sqrt();
+    let th = 2.0 * sin.atan2(cos);
+    let (ph, la, gamma) = if cos < EPSILON {
+        // Only `lambda - phi` is determined; take `phi = 0`.
+        let gamma = arg(c.0, c.1);
+        (0.0, arg(-b.0, -b.1) - gamma, gamma)
+    } else if sin < EPSILON {
+        // Only `phi + lambda` is determined; take `phi = 0`.
+        let gamma = arg(a.0, a.1);
+        (0.0, arg(d.0, d.1) - gamma, gamma)
+    } else {
+        let gamma = arg(a.0, a.1);
+        (arg(c.0, c.1) - gamma, arg(-b.0, -b.1) - gamma, gamma)
+    };
+    let wrap = |x: f64| {
+        let x = x - 2.0 * PI * ((x + PI) / (2.0 * PI)).floor();
+        if x.abs() < EPSILON { 0.0 } else if x <= -PI + EPSILON { PI } else { x }
+    };
+    (th, wrap(ph), wrap(la), wrap(gamma))
+}
@@ -0,0 +1,162 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use qrack_error::QrackError;
+
+// Capacity of the "controls" array in Qrack's "_QrackTimeEvolveOpHeader"
+const HEADER_CONTROLS: usize = 32;
+
+// Most controls per term; each term marshals one 2x2 matrix per control
+// permutation, so 16 controls already take 4 MiB.
+pub const MAX_TIME_EVOLVE_CONTROLS: usize = 16;
+
+#[repr(C)]
+pub(crate) struct TimeEvolveOpHeader {
+    // Layout of Qrack's "_QrackTimeEvolveOpHeader"
+    target: u64,
+    control_len: u64,
+    controls: [u64; HEADER_CONTROLS]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HamiltonianTerm {
+    // Single (optionally controlled) 2x2 term of a Hamiltonian
+    //
+    // Attributes:
+    //     target(u64): qubit the matrix acts on
+    //     controls(Vec<u64>): control qubits
+    //     anti(bool): if true, the term is active when all controls are `|0>`,
+    //         rather than `|1>`
+    //     matrix([f64;8]): Hermitian 2x2 generator, row-major complex list
+    pub target: u64,
+    pub controls: Vec<u64>,
+    pub anti: bool,
+    pub matrix: [f64;8]
+}
+
+impl HamiltonianTerm {
+    pub fn new(target: u64, matrix: [f64;8]) -> Self {
+        Self{ target, controls: Vec::new(), anti: false, matrix }
+    }
+
+    pub fn controlled(target: u64, controls: Vec<u64>, anti: bool, matrix: [f64;8]) -> Self {
+        Self{ target, controls, anti, matrix }
+    }
+
+    pub fn validate(&self) -> Result<(), QrackError> {
+        // Check the term can be marshaled for Qrack
+        //
+        // Raises:
+        //     InvalidArgument: too many controls, target among controls,
+        //         repeated control, or non-Hermitian matrix.
+
+        if self.controls.len() > MAX_TIME_EVOLVE_CONTROLS {
+            return Err(QrackError::InvalidArgument(format!(
+                "Hamiltonian term has {} controls, at most {} are supported",
+                self.controls.len(), MAX_TIME_EVOLVE_CONTROLS)));
+        }
+        for (i, c) in self.controls.iter().enumerate() {
+            if *c == self.target {
+                return Err(QrackError::InvalidArgument(format!(
+                    "Hamiltonian term target {} is also a control", c)));
+            }
+            if self.controls[..i].contains(c) {
+                return Err(QrackError::InvalidArgument(format!(
+                    "Hamiltonian term repeats control {}", c)));
+            }
+        }
+        let m = &self.matrix;
+        // Diagonal must be real, and the off-diagonal entries conjugate.
+        let tol = 1e-9;
+        if m[1].abs() > tol || m[7].abs() > tol
+            || (m[2] - m[4]).abs() > tol || (m[3] + m[5]).abs() > tol {
+            return Err(QrackError::InvalidArgument(
+                "Hamiltonian term matrix is not Hermitian".to_string()));
+        }
+        Ok(())
+    }
+
+    fn max_qubit(&self) -> u64 {
+        self.controls.iter().fold(self.target, |a, c| a.max(*c))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hamiltonian {
+    // Sum of 2x2 terms, for `QrackSimulator::time_evolve()`
+    //
+    // Attributes:
+    //     terms(Vec<HamiltonianTerm>): validated terms
+    terms: Vec<HamiltonianTerm>
+}
+
+impl Hamiltonian {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_terms(terms: Vec<HamiltonianTerm>) -> Result<Self, QrackError> {
+        let mut h = Self::new();
+        for term in terms {
+            h.push(term)?;
+        }
+        Ok(h)
+    }
+
+    pub fn push(&mut self, term: HamiltonianTerm) -> Result<(), QrackError> {
+        // Add a term
+        //
+        // Raises:
+        //     InvalidArgument: the term failed validation.
+
+        term.validate()?;
+        self.terms.push(term);
+        Ok(())
+    }
+
+    pub fn terms(&self) -> &[HamiltonianTerm] {
+        &self.terms
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn qubit_count(&self) -> u64 {
+        // Minimum simulator width the terms fit in
+        self.terms.iter().map(|t| t.max_qubit() + 1).max().unwrap_or(0)
+    }
+
+    pub(crate) fn marshal(&self) -> (Vec<TimeEvolveOpHeader>, Vec<f64>) {
+        // Headers and flat matrix buffer for "TimeEvolve()"
+        //
+        // Qrack reads each op as uniformly controlled: one 2x2 matrix per
+        // control permutation. The term's matrix goes at the permutation
+        // that activates it, (all controls set, or all clear for "anti"),
+        // and every other permutation gets the zero generator.
+        let mut headers = Vec::with_capacity(self.terms.len());
+        let mut mtrx = Vec::new();
+        for term in &self.terms {
+            let mut controls = [0u64; HEADER_CONTROLS];
+            controls[..term.controls.len()].copy_from_slice(&term.controls);
+            headers.push(TimeEvolveOpHeader{
+                target: term.target,
+                control_len: term.controls.len() as u64,
+                controls
+            });
+
+            let perms = 1usize << term.controls.len();
+            let active = if term.anti { 0 } else { perms - 1 };
+            let offset = mtrx.len();
+            mtrx.resize(offset + 8 * perms, 0.0);
+            mtrx[(offset + 8 * active)..(offset + 8 * (active + 1))].copy_from_slice(&term.matrix);
+        }
+        (headers, mtrx)
+    }
+}
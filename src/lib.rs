@@ -1,8 +1,32 @@
+pub extern crate num_complex;
+#[cfg(feature = "serde")]
+extern crate serde;
+
+mod circuit_drawing;
+pub mod circuit_instruction;
+pub mod circuit_options;
+pub mod circuit_result;
+pub mod circuit_stats;
+pub mod coupling_map;
+pub mod dynamic_circuit;
+pub mod gate_matrix;
+pub mod matrix;
+pub mod parameter;
+pub mod parameterized_circuit;
+pub mod pass_manager;
 pub mod pauli;
+pub mod pauli_string;
+pub mod qasm;
+pub mod hamiltonian;
 pub mod neuron_activation_fn;
 pub mod qrack_error;
+pub mod qrack_real;
 #[allow(non_camel_case_types)]
 pub mod qrack_system;
 pub mod qrack_simulator;
+pub mod recording_simulator;
+pub mod routing;
+pub mod simulator_builder;
 pub mod qrack_neuron;
 pub mod qrack_circuit;
+pub mod transpile;
@@ -0,0 +1,92 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::ops::Index;
+
+use num_complex::Complex;
+use qrack_error::QrackError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    // Square complex matrix, in row-major order
+    //
+    // As an operator on qubits, bit "q" of a row or column index is the state
+    // of qubit "q", as in `QrackSimulator::out_ket()`.
+    //
+    // Attributes:
+    //     dimension(usize): row and column count
+    //     data(Vec<Complex<f64>>): `dimension^2` elements
+    dimension: usize,
+    data: Vec<Complex<f64>>
+}
+
+impl Matrix {
+    pub fn new(dimension: usize, data: Vec<Complex<f64>>) -> Result<Self, QrackError> {
+        // Raises:
+        //     LengthMismatch: "data" doesn't have `dimension^2` elements.
+        if data.len() != dimension * dimension {
+            return Err(QrackError::LengthMismatch{ expected: dimension * dimension, actual: data.len() });
+        }
+        Ok(Matrix{ dimension, data })
+    }
+
+    pub fn identity(dimension: usize) -> Self {
+        let mut data = vec![Complex::new(0.0, 0.0); dimension * dimension];
+        for i in 0..dimension {
+            data[i * (dimension + 1)] = Complex::new(1.0, 0.0);
+        }
+        Matrix{ dimension, data }
+    }
+
+    pub(crate) fn from_columns(columns: Vec<Vec<Complex<f64>>>) -> Self {
+        let dimension = columns.len();
+        let data = (0..dimension).flat_map(|r| columns.iter().map(move |c| c[r])).collect();
+        Matrix{ dimension, data }
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn data(&self) -> &[Complex<f64>] {
+        &self.data
+    }
+
+    pub fn column(&self, c: usize) -> Vec<Complex<f64>> {
+        (0..self.dimension).map(|r| self[(r, c)]).collect()
+    }
+
+    pub fn approx_eq(&self, other: &Matrix, up_to_global_phase: bool, tol: f64) -> bool {
+        // Whether every element differs by at most "tol"
+        //
+        // Args:
+        //     other(&Matrix): matrix to compare
+        //     up_to_global_phase(bool): first rotate "other" by the phase that
+        //         best aligns it with this matrix
+        //     tol(f64): largest allowed element difference
+        if self.dimension != other.dimension {
+            return false;
+        }
+        let phase = if up_to_global_phase { global_phase(&self.data, &other.data) } else { Complex::new(1.0, 0.0) };
+        self.data.iter().zip(&other.data).all(|(a, b)| (a - b * phase).norm() <= tol)
+    }
+}
+
+impl Index<(usize, usize)> for Matrix {
+    type Output = Complex<f64>;
+    fn index(&self, (r, c): (usize, usize)) -> &Complex<f64> {
+        &self.data[r * self.dimension + c]
+    }
+}
+
+pub(crate) fn global_phase(a: &[Complex<f64>], b: &[Complex<f64>]) -> Complex<f64> {
+    // Unit phase "p" minimizing `|a - p*b|`, (1 if "a" and "b" are orthogonal)
+    let overlap: Complex<f64> = a.iter().zip(b).map(|(x, y)| y.conj() * x).sum();
+    if overlap.norm() > 0.0 {
+        overlap / overlap.norm()
+    } else {
+        Complex::new(1.0, 0.0)
+    }
+}
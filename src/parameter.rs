@@ -0,0 +1,248 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+// Symbolic gate angles, for ParameterizedCircuit.
+//
+// Expressions are linear in their parameters, and build with the usual
+// operators, e.g. `2.0 * &theta + 0.1`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use qrack_error::QrackError;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Parameter {
+    // Named free angle; parameters with equal names are the same parameter
+    //
+    // Attributes:
+    //     name(String): parameter name
+    name: String
+}
+
+impl Parameter {
+    pub fn new(name: &str) -> Self {
+        Self{ name: name.to_string() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParameterExpression {
+    // `constant + sum(coefficient * parameter)`
+    //
+    // Attributes:
+    //     constant(f64): constant term
+    //     terms(Vec<(Parameter, f64)>): (parameter, coefficient) pairs, one per parameter
+    constant: f64,
+    terms: Vec<(Parameter, f64)>
+}
+
+impl ParameterExpression {
+    pub fn constant(&self) -> f64 {
+        self.constant
+    }
+
+    pub fn terms(&self) -> &[(Parameter, f64)] {
+        &self.terms
+    }
+
+    pub fn parameters(&self) -> impl Iterator<Item = &Parameter> {
+        self.terms.iter().map(|t| &t.0)
+    }
+
+    pub fn is_constant(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    pub fn evaluate(&self, values: &HashMap<Parameter, f64>) -> Result<f64, QrackError> {
+        // Value with every parameter bound
+        //
+        // Raises:
+        //     InvalidArgument: a parameter has no value.
+        let mut v = self.constant;
+        for (p, c) in &self.terms {
+            match values.get(p) {
+                Some(x) => v += c * x,
+                None => return Err(QrackError::InvalidArgument(format!("parameter \"{}\" is not bound", p)))
+            }
+        }
+        Ok(v)
+    }
+
+    fn scale(mut self, k: f64) -> Self {
+        self.constant *= k;
+        for t in &mut self.terms {
+            t.1 *= k;
+        }
+        self
+    }
+
+    fn add_scaled(mut self, other: ParameterExpression, k: f64) -> Self {
+        self.constant += k * other.constant;
+        for (p, c) in other.terms {
+            match self.terms.iter_mut().find(|t| t.0 == p) {
+                Some(t) => t.1 += k * c,
+                None => self.terms.push((p, k * c))
+            }
+        }
+        self.terms.retain(|t| t.1 != 0.0);
+        self
+    }
+}
+
+impl fmt::Display for ParameterExpression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (p, c)) in self.terms.iter().enumerate() {
+            let sign = if *c < 0.0 { "-" } else if i > 0 { "+" } else { "" };
+            let space = if i > 0 { " " } else { "" };
+            write!(f, "{}{}{}", space, sign, space)?;
+            if c.abs() != 1.0 {
+                write!(f, "{}*", c.abs())?;
+            }
+            write!(f, "{}", p)?;
+        }
+        if self.terms.is_empty() {
+            write!(f, "{}", self.constant)
+        } else if self.constant != 0.0 {
+            write!(f, " {} {}", if self.constant < 0.0 { '-' } else { '+' }, self.constant.abs())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl From<f64> for ParameterExpression {
+    fn from(constant: f64) -> Self {
+        Self{ constant, terms: Vec::new() }
+    }
+}
+
+impl From<Parameter> for ParameterExpression {
+    fn from(p: Parameter) -> Self {
+        Self{ constant: 0.0, terms: vec![(p, 1.0)] }
+    }
+}
+
+impl<'a> From<&'a Parameter> for ParameterExpression {
+    fn from(p: &'a Parameter) -> Self {
+        p.clone().into()
+    }
+}
+
+impl<R: Into<ParameterExpression>> Add<R> for ParameterExpression {
+    type Output = ParameterExpression;
+    fn add(self, rhs: R) -> ParameterExpression {
+        self.add_scaled(rhs.into(), 1.0)
+    }
+}
+
+impl<R: Into<ParameterExpression>> Sub<R> for ParameterExpression {
+    type Output = ParameterExpression;
+    fn sub(self, rhs: R) -> ParameterExpression {
+        self.add_scaled(rhs.into(), -1.0)
+    }
+}
+
+impl Mul<f64> for ParameterExpression {
+    type Output = ParameterExpression;
+    fn mul(self, rhs: f64) -> ParameterExpression {
+        self.scale(rhs)
+    }
+}
+
+impl Div<f64> for ParameterExpression {
+    type Output = ParameterExpression;
+    fn div(self, rhs: f64) -> ParameterExpression {
+        self.scale(1.0 / rhs)
+    }
+}
+
+impl Neg for ParameterExpression {
+    type Output = ParameterExpression;
+    fn neg(self) -> ParameterExpression {
+        self.scale(-1.0)
+    }
+}
+
+impl Mul<ParameterExpression> for f64 {
+    type Output = ParameterExpression;
+    fn mul(self, rhs: ParameterExpression) -> ParameterExpression {
+        rhs.scale(self)
+    }
+}
+
+impl Add<ParameterExpression> for f64 {
+    type Output = ParameterExpression;
+    fn add(self, rhs: ParameterExpression) -> ParameterExpression {
+        rhs + self
+    }
+}
+
+// Parameters, by value or reference, behave as single-term expressions.
+macro_rules! parameter_ops {
+    ($($t:ty),*) => {$(
+        impl<'a, R: Into<ParameterExpression>> Add<R> for $t {
+            type Output = ParameterExpression;
+            fn add(self, rhs: R) -> ParameterExpression {
+                ParameterExpression::from(self) + rhs
+            }
+        }
+
+        impl<'a, R: Into<ParameterExpression>> Sub<R> for $t {
+            type Output = ParameterExpression;
+            fn sub(self, rhs: R) -> ParameterExpression {
+                ParameterExpression::from(self) - rhs
+            }
+        }
+
+        impl<'a> Mul<f64> for $t {
+            type Output = ParameterExpression;
+            fn mul(self, rhs: f64) -> ParameterExpression {
+                ParameterExpression::from(self) * rhs
+            }
+        }
+
+        impl<'a> Div<f64> for $t {
+            type Output = ParameterExpression;
+            fn div(self, rhs: f64) -> ParameterExpression {
+                ParameterExpression::from(self) / rhs
+            }
+        }
+
+        impl<'a> Neg for $t {
+            type Output = ParameterExpression;
+            fn neg(self) -> ParameterExpression {
+                -ParameterExpression::from(self)
+            }
+        }
+
+        impl<'a> Mul<$t> for f64 {
+            type Output = ParameterExpression;
+            fn mul(self, rhs: $t) -> ParameterExpression {
+                ParameterExpression::from(rhs) * self
+            }
+        }
+
+        impl<'a> Add<$t> for f64 {
+            type Output = ParameterExpression;
+            fn add(self, rhs: $t) -> ParameterExpression {
+                ParameterExpression::from(rhs) + self
+            }
+        }
+    )*}
+}
+
+parameter_ops!(Parameter, &'a Parameter);
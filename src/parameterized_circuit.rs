@@ -0,0 +1,216 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::collections::{BTreeSet, HashMap};
+
+use gate_matrix;
+use parameter::{Parameter, ParameterExpression};
+use pauli::Pauli;
+use qrack_circuit::{all_ones, QrackCircuit};
+use qrack_error::QrackError;
+use qrack_simulator::QrackSimulator;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Payload {
+    Fixed([f64;8]),
+    R(Pauli, ParameterExpression),
+    U(ParameterExpression, ParameterExpression, ParameterExpression)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    // Payload applied if "c" matches "p", as `QrackCircuit::ucmtrx()`
+    Gate { c: Vec<u64>, p: u64, q: u64, payload: Payload },
+    Swap(u64, u64)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParameterizedCircuit {
+    // Circuit whose rotation angles may be expressions of named parameters
+    //
+    // Binding values to the parameters produces a QrackCircuit, so one
+    // ParameterizedCircuit serves every step of a variational optimizer.
+    //
+    // Attributes:
+    //     ops(Vec<Op>): gates, in application order
+    ops: Vec<Op>
+}
+
+impl ParameterizedCircuit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn parameters(&self) -> Vec<Parameter> {
+        // Distinct parameters, sorted by name
+        let mut out = BTreeSet::new();
+        for op in &self.ops {
+            match op {
+                Op::Gate{ payload: Payload::R(_, ph), .. } => out.extend(ph.parameters().cloned()),
+                Op::Gate{ payload: Payload::U(th, ph, la), .. } => {
+                    out.extend(th.parameters().chain(ph.parameters()).chain(la.parameters()).cloned())
+                },
+                _ => {}
+            }
+        }
+        out.into_iter().collect()
+    }
+
+    pub fn swap(&mut self, q1: u64, q2: u64) {
+        self.ops.push(Op::Swap(q1, q2));
+    }
+
+    pub fn mtrx(&mut self, m: &[f64;8], q: u64) {
+        // Fixed operation from matrix.
+        //
+        // Args:
+        //     m(&[f64;8]): row-major complex list representing the operator.
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.ucmtrx(Vec::new(), m, q, 0)
+    }
+
+    pub fn ucmtrx(&mut self, c: Vec<u64>, m: &[f64;8], q: u64, p: u64) {
+        // Fixed operation, applied if all control qubits match 'p' permutation
+        // by bit order
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits
+        //     m(&[f64;8]): row-major complex list representing the operator.
+        //     q(u64): target qubit
+        //     p(u64): permutation of list of control qubits
+        self.ops.push(Op::Gate{ c, p, q, payload: Payload::Fixed(*m) });
+    }
+
+    pub fn r<E: Into<ParameterExpression>>(&mut self, b: Pauli, ph: E, q: u64) {
+        // Rotation gate, `e^{-i*ph/2*b}`
+        //
+        // Args:
+        //     b(Pauli): Pauli basis
+        //     ph(Into<ParameterExpression>): rotation angle
+        //     q(u64): target qubit
+        self.ucr(b, ph, Vec::new(), q, 0)
+    }
+
+    pub fn mcr<E: Into<ParameterExpression>>(&mut self, b: Pauli, ph: E, c: Vec<u64>, q: u64) {
+        // Rotation gate, applied if all control qubits are `|1>`
+        let p = all_ones(c.len());
+        self.ucr(b, ph, c, q, p)
+    }
+
+    pub fn macr<E: Into<ParameterExpression>>(&mut self, b: Pauli, ph: E, c: Vec<u64>, q: u64) {
+        // Rotation gate, applied if all control qubits are `|0>`
+        self.ucr(b, ph, c, q, 0)
+    }
+
+    pub fn ucr<E: Into<ParameterExpression>>(&mut self, b: Pauli, ph: E, c: Vec<u64>, q: u64, p: u64) {
+        // Rotation gate, applied if all control qubits match 'p' permutation
+        // by bit order
+        self.ops.push(Op::Gate{ c, p, q, payload: Payload::R(b, ph.into()) });
+    }
+
+    pub fn u<A, B, C>(&mut self, q: u64, th: A, ph: B, la: C)
+        where A: Into<ParameterExpression>, B: Into<ParameterExpression>, C: Into<ParameterExpression>
+    {
+        // General unitary gate, as `gate_matrix::u()`
+        //
+        // Args:
+        //     q(u64): target qubit
+        //     th, ph, la(Into<ParameterExpression>): Euler angles
+        self.ucu(Vec::new(), q, th, ph, la, 0)
+    }
+
+    pub fn mcu<A, B, C>(&mut self, c: Vec<u64>, q: u64, th: A, ph: B, la: C)
+        where A: Into<ParameterExpression>, B: Into<ParameterExpression>, C: Into<ParameterExpression>
+    {
+        // General unitary gate, applied if all control qubits are `|1>`
+        let p = all_ones(c.len());
+        self.ucu(c, q, th, ph, la, p)
+    }
+
+    pub fn macu<A, B, C>(&mut self, c: Vec<u64>, q: u64, th: A, ph: B, la: C)
+        where A: Into<ParameterExpression>, B: Into<ParameterExpression>, C: Into<ParameterExpression>
+    {
+        // General unitary gate, applied if all control qubits are `|0>`
+        self.ucu(c, q, th, ph, la, 0)
+    }
+
+    pub fn ucu<A, B, C>(&mut self, c: Vec<u64>, q: u64, th: A, ph: B, la: C, p: u64)
+        where A: Into<ParameterExpression>, B: Into<ParameterExpression>, C: Into<ParameterExpression>
+    {
+        // General unitary gate, applied if all control qubits match 'p'
+        // permutation by bit order
+        self.ops.push(Op::Gate{ c, p, q, payload: Payload::U(th.into(), ph.into(), la.into()) });
+    }
+
+    pub fn bind(&self, values: &HashMap<Parameter, f64>) -> Result<QrackCircuit, QrackError> {
+        // Circuit with every parameter replaced by its value
+        //
+        // Raises:
+        //     InvalidArgument: a parameter has no value.
+        let circ = QrackCircuit::new();
+        for op in &self.ops {
+            match op {
+                Op::Gate{ c, p, q, payload } => {
+                    let m = payload.matrix(values)?;
+                    if c.is_empty() {
+                        circ.mtrx(&m, *q);
+                    } else {
+                        circ.ucmtrx(c.clone(), &m, *q, *p);
+                    }
+                },
+                Op::Swap(q1, q2) => circ.swap(*q1, *q2)
+            }
+        }
+        Ok(circ)
+    }
+
+    pub fn run_with(&self, qsim: &QrackSimulator, values: &HashMap<Parameter, f64>) -> Result<(), QrackError> {
+        // Apply the bound gates directly to a simulator, without building a
+        // QrackCircuit
+        //
+        // Every parameter is checked before any gate is applied.
+        //
+        // Raises:
+        //     InvalidArgument: a parameter has no value.
+        //     Native: Qrack reported an error code.
+        let matrices = self.ops.iter()
+            .map(|op| match op {
+                Op::Gate{ payload, .. } => payload.matrix(values),
+                Op::Swap(..) => Ok(gate_matrix::I)
+            })
+            .collect::<Result<Vec<[f64;8]>, QrackError>>()?;
+        for (op, m) in self.ops.iter().zip(matrices) {
+            match op {
+                Op::Gate{ c, p, q, .. } => {
+                    if c.is_empty() {
+                        qsim.mtrx(&m, *q)?;
+                    } else {
+                        qsim.ucmtrx(c.clone(), &m, *q, *p)?;
+                    }
+                },
+                Op::Swap(q1, q2) => qsim.swap(*q1, *q2)?
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Payload {
+    fn matrix(&self, values: &HashMap<Parameter, f64>) -> Result<[f64;8], QrackError> {
+        Ok(match self {
+            Payload::Fixed(m) => *m,
+            Payload::R(b, ph) => gate_matrix::r(*b, ph.evaluate(values)?),
+            Payload::U(th, ph, la) => gate_matrix::u(th.evaluate(values)?, ph.evaluate(values)?, la.evaluate(values)?)
+        })
+    }
+}
@@ -0,0 +1,222 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+// Rust-side optimization of circuit instructions.
+//
+// Every pass scans the instructions in order, and merges each one into an
+// earlier instruction on the same target and controls, (multiplying payloads
+// by permutation), when nothing in between stops it:
+//     - CancelInverses: no gate in between shares a qubit, and the merged
+//       payloads are all identity,
+//     - FuseSingleQubit: no gate in between shares the qubit, and both are
+//       uncontrolled,
+//     - Commute: every gate in between shares no qubit, or commutes with it,
+// and RemoveNearIdentity drops payloads within the tolerance of identity.
+// Merged identity payloads are dropped too. Uncontrolled payloads count as
+// identity up to global phase; controlled payloads must match it exactly.
+
+use std::collections::BTreeMap;
+
+use circuit_instruction::CircuitInstruction;
+use gate_matrix;
+use qrack_circuit::QrackCircuit;
+use qrack_error::QrackError;
+use transpile::to_circuit;
+
+// Pipeline repetitions, at most, while passes keep removing gates
+const MAX_ROUNDS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Pass {
+    RemoveNearIdentity,
+    CancelInverses,
+    FuseSingleQubit,
+    Commute
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PassReport {
+    // Gates, (payloads, as counted by CircuitStats), before and after, and
+    // removed by each pass
+    //
+    // Attributes:
+    //     gates_before(usize): gates in the input
+    //     gates_after(usize): gates in the output
+    //     removed(BTreeMap<Pass, usize>): gates removed per pass, over every round
+    pub gates_before: usize,
+    pub gates_after: usize,
+    pub removed: BTreeMap<Pass, usize>
+}
+
+impl PassReport {
+    pub fn removed_by(&self, pass: Pass) -> usize {
+        self.removed.get(&pass).cloned().unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassManager {
+    // Ordered pipeline of passes, repeated while it removes gates
+    //
+    // Attributes:
+    //     passes(Vec<Pass>): passes, in order
+    //     tolerance(f64): largest matrix element difference treated as equal
+    passes: Vec<Pass>,
+    tolerance: f64
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        // Every pass
+        Self{
+            passes: vec![Pass::RemoveNearIdentity, Pass::CancelInverses, Pass::FuseSingleQubit, Pass::Commute],
+            tolerance: gate_matrix::TOLERANCE
+        }
+    }
+}
+
+impl PassManager {
+    pub fn new() -> Self {
+        // Empty pipeline
+        Self{ passes: Vec::new(), tolerance: gate_matrix::TOLERANCE }
+    }
+
+    pub fn pass(mut self, pass: Pass) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn passes(&self) -> &[Pass] {
+        &self.passes
+    }
+
+    pub fn run(&self, instructions: &[CircuitInstruction]) -> (Vec<CircuitInstruction>, PassReport) {
+        // Optimized instructions, equal to the input up to global phase
+        let mut current = instructions.to_vec();
+        let mut report = PassReport{ gates_before: gate_count(&current), ..PassReport::default() };
+        for _ in 0..MAX_ROUNDS {
+            let mut changed = false;
+            for pass in &self.passes {
+                let before = gate_count(&current);
+                current = match pass {
+                    Pass::RemoveNearIdentity => current.into_iter().filter_map(|i| self.without_identities(i)).collect(),
+                    _ => self.merge_scan(&current, *pass)
+                };
+                let removed = before - gate_count(&current);
+                *report.removed.entry(*pass).or_insert(0) += removed;
+                changed |= removed > 0;
+            }
+            if !changed {
+                break;
+            }
+        }
+        report.gates_after = gate_count(&current);
+        (current, report)
+    }
+
+    pub fn run_circuit(&self, circuit: &QrackCircuit) -> Result<(QrackCircuit, PassReport), QrackError> {
+        // Optimized copy of a circuit
+        //
+        // Raises:
+        //     Io: the circuit couldn't be read back from Qrack.
+        let (instructions, report) = self.run(&circuit.instructions()?);
+        Ok((to_circuit(&instructions), report))
+    }
+
+    fn merge_scan(&self, instructions: &[CircuitInstruction], pass: Pass) -> Vec<CircuitInstruction> {
+        let mut out: Vec<CircuitInstruction> = Vec::new();
+        for g in instructions {
+            let mut placed = false;
+            for k in (0..out.len()).rev() {
+                let prev = &out[k];
+                if prev.target == g.target && prev.controls == g.controls {
+                    let merged = self.without_identities(merge(prev, g));
+                    let accept = match pass {
+                        Pass::CancelInverses => merged.is_none(),
+                        Pass::FuseSingleQubit => g.controls.is_empty(),
+                        _ => true
+                    };
+                    if accept {
+                        match merged {
+                            Some(m) => out[k] = m,
+                            None => {
+                                out.remove(k);
+                            }
+                        }
+                        placed = true;
+                    }
+                    break;
+                }
+                let shared = g.qubits().iter().any(|q| prev.qubits().contains(q));
+                if shared && !(pass == Pass::Commute && self.commutes(prev, g)) {
+                    break;
+                }
+            }
+            if !placed {
+                out.push(g.clone());
+            }
+        }
+        out
+    }
+
+    fn without_identities(&self, mut i: CircuitInstruction) -> Option<CircuitInstruction> {
+        // "i" without its identity payloads, (None if none are left)
+        let controlled = i.is_controlled();
+        i.payloads.retain(|_, m| !is_identity(m, !controlled, self.tolerance));
+        if i.payloads.is_empty() { None } else { Some(i) }
+    }
+
+    fn commutes(&self, a: &CircuitInstruction, b: &CircuitInstruction) -> bool {
+        // Sufficient condition, qubit by qubit: controls commute with
+        // controls, a control commutes with a diagonal target, and shared
+        // targets need every pair of payloads to commute
+        let diagonal = |i: &CircuitInstruction| i.payloads.values().all(|m| is_diagonal(m, self.tolerance));
+        let a_qubits = a.qubits();
+        b.qubits().iter().filter(|q| a_qubits.contains(q)).all(|q| {
+            match (*q == a.target, *q == b.target) {
+                (false, false) => true,
+                (false, true) => diagonal(b),
+                (true, false) => diagonal(a),
+                (true, true) => a.payloads.values().all(|x| b.payloads.values().all(|y| {
+                    gate_matrix::approx_eq(&gate_matrix::mul(x, y), &gate_matrix::mul(y, x), self.tolerance)
+                }))
+            }
+        })
+    }
+}
+
+fn gate_count(instructions: &[CircuitInstruction]) -> usize {
+    instructions.iter().map(|i| i.payloads.len()).sum()
+}
+
+fn merge(first: &CircuitInstruction, then: &CircuitInstruction) -> CircuitInstruction {
+    // "first" followed by "then", on the same target and controls
+    let mut out = first.clone();
+    for (p, m) in &then.payloads {
+        let prev = out.payloads.get(p).cloned().unwrap_or(gate_matrix::I);
+        out.payloads.insert(*p, gate_matrix::mul(m, &prev));
+    }
+    out
+}
+
+fn is_diagonal(m: &[f64;8], tol: f64) -> bool {
+    m[2].hypot(m[3]) <= tol && m[4].hypot(m[5]) <= tol
+}
+
+fn is_identity(m: &[f64;8], up_to_global_phase: bool, tol: f64) -> bool {
+    if !is_diagonal(m, tol) {
+        return false;
+    }
+    if up_to_global_phase {
+        (m[0] - m[6]).hypot(m[1] - m[7]) <= tol && (m[0].hypot(m[1]) - 1.0).abs() <= tol
+    } else {
+        (m[0] - 1.0).hypot(m[1]) <= tol && (m[6] - 1.0).hypot(m[7]) <= tol
+    }
+}
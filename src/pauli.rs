@@ -5,7 +5,8 @@
 // Use of this source code is governed by an MIT-style license that can be
 // found in the LICENSE file or at https://opensource.org/licenses/MIT.
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(i32)]
 pub enum Pauli {
     // Pauli Identity operator. Corresponds to Q# constant "PauliI."
     PauliI = 0,
@@ -0,0 +1,316 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::fmt;
+use std::str::FromStr;
+
+use pauli::Pauli;
+use qrack_error::QrackError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauliString {
+    // Real coefficient times a tensor product of Pauli operators
+    //
+    // Qubits absent from "paulis" carry the identity.
+    //
+    // Attributes:
+    //     coefficient(f64): real coefficient
+    //     paulis(Vec<(u64, Pauli)>): (qubit, operator) factors, one per qubit
+    coefficient: f64,
+    paulis: Vec<(u64, Pauli)>
+}
+
+impl PauliString {
+    pub fn new(coefficient: f64, paulis: Vec<(u64, Pauli)>) -> Result<Self, QrackError> {
+        // Raises:
+        //     InvalidArgument: a qubit appears in more than one factor.
+
+        let mut paulis: Vec<(u64, Pauli)> = paulis.into_iter().filter(|p| p.1 != Pauli::PauliI).collect();
+        paulis.sort_by_key(|p| p.0);
+        if let Some(w) = paulis.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(QrackError::InvalidArgument(format!("qubit {} appears twice in Pauli string", w[0].0)));
+        }
+        Ok(Self{ coefficient, paulis })
+    }
+
+    pub fn coefficient(&self) -> f64 {
+        self.coefficient
+    }
+
+    pub fn paulis(&self) -> &[(u64, Pauli)] {
+        // Non-identity factors, sorted by qubit
+        &self.paulis
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.paulis.is_empty()
+    }
+
+    fn pauli_at(&self, q: u64) -> Pauli {
+        match self.paulis.binary_search_by_key(&q, |p| p.0) {
+            Ok(i) => self.paulis[i].1,
+            Err(_) => Pauli::PauliI
+        }
+    }
+
+    pub fn qubit_wise_commutes(&self, other: &PauliString) -> bool {
+        // Whether, on every qubit, the factors are equal or one is identity,
+        // (so both terms can be measured in one shared basis)
+        self.paulis.iter().all(|(q, p)| {
+            let o = other.pauli_at(*q);
+            o == Pauli::PauliI || o == *p
+        })
+    }
+
+    pub fn commutes(&self, other: &PauliString) -> bool {
+        // Whether the operators commute, (an even number of qubits anticommute)
+        self.paulis.iter()
+            .filter(|(q, p)| {
+                let o = other.pauli_at(*q);
+                o != Pauli::PauliI && o != *p
+            })
+            .count() % 2 == 0
+    }
+
+    fn fmt_with_coefficient(&self, f: &mut fmt::Formatter, coefficient: f64) -> fmt::Result {
+        write!(f, "{}", coefficient)?;
+        for (i, (q, p)) in self.paulis.iter().enumerate() {
+            write!(f, "{}{}{}", if i == 0 { "*" } else { " " }, pauli_char(*p), q)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PauliString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_with_coefficient(f, self.coefficient)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PauliSum {
+    // Observable as a real-weighted sum of Pauli strings
+    //
+    // Parses from strings like "0.5*X0 Z3 - 1.2*Y1".
+    //
+    // Attributes:
+    //     terms(Vec<PauliString>): summands
+    terms: Vec<PauliString>
+}
+
+impl PauliSum {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_terms(terms: Vec<PauliString>) -> Self {
+        Self{ terms }
+    }
+
+    pub fn push(&mut self, term: PauliString) {
+        self.terms.push(term);
+    }
+
+    pub fn terms(&self) -> &[PauliString] {
+        &self.terms
+    }
+
+    pub fn simplify(&self) -> PauliSum {
+        // Merge terms with equal operators, and drop terms with 0 coefficient
+        let mut terms: Vec<PauliString> = Vec::new();
+        for t in &self.terms {
+            match terms.iter_mut().find(|u| u.paulis == t.paulis) {
+                Some(u) => u.coefficient += t.coefficient,
+                None => terms.push(t.clone())
+            }
+        }
+        terms.retain(|t| t.coefficient != 0.0);
+        PauliSum{ terms }
+    }
+
+    pub fn commuting_groups(&self) -> Vec<Vec<usize>> {
+        // Greedily partition terms into qubit-wise commuting groups
+        //
+        // Returns:
+        //     groups of indices into `terms()`; every pair in a group can be
+        //     measured in one shared basis
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (i, t) in self.terms.iter().enumerate() {
+            let found = groups.iter_mut()
+                .find(|g| g.iter().all(|j| self.terms[*j].qubit_wise_commutes(t)));
+            match found {
+                Some(g) => g.push(i),
+                None => groups.push(vec![i])
+            }
+        }
+        groups
+    }
+}
+
+impl fmt::Display for PauliSum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.terms.is_empty() {
+            return write!(f, "0");
+        }
+        for (i, t) in self.terms.iter().enumerate() {
+            if i == 0 {
+                t.fmt_with_coefficient(f, t.coefficient)?;
+            } else {
+                // Written so the output parses back with `from_str()`
+                write!(f, " {} ", if t.coefficient < 0.0 { '-' } else { '+' })?;
+                t.fmt_with_coefficient(f, t.coefficient.abs())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for PauliSum {
+    type Err = QrackError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Grammar, (whitespace is insignificant between tokens):
+        //     sum    := [sign] term (sign term)*
+        //     term   := [number ["*"]] factor (["*"] factor)* | number
+        //     factor := ("I" | "X" | "Y" | "Z") qubit
+        let mut p = Parser{ chars: s.chars().collect(), pos: 0 };
+        let mut sum = PauliSum::new();
+        p.skip_ws();
+        let mut sign = p.sign().unwrap_or(1.0);
+        loop {
+            sum.push(p.term(sign)?);
+            p.skip_ws();
+            if p.at_end() {
+                break;
+            }
+            sign = p.sign().ok_or_else(|| p.error("expected '+' or '-'"))?;
+        }
+        Ok(sum)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.chars.len()
+    }
+
+    fn skip_ws(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, msg: &str) -> QrackError {
+        QrackError::InvalidArgument(format!("{} at column {} of Pauli sum", msg, self.pos + 1))
+    }
+
+    fn sign(&mut self) -> Option<f64> {
+        let s = match self.peek() {
+            Some('+') => 1.0,
+            Some('-') => -1.0,
+            _ => return None
+        };
+        self.pos += 1;
+        Some(s)
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn slice(&self, start: usize) -> String {
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn number(&mut self) -> Result<f64, QrackError> {
+        let start = self.pos;
+        self.digits();
+        if self.peek() == Some('.') {
+            self.pos += 1;
+            self.digits();
+        }
+        if let Some('e') | Some('E') = self.peek() {
+            self.pos += 1;
+            if let Some('+') | Some('-') = self.peek() {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("expected exponent digits"));
+            }
+        }
+        let text = self.slice(start);
+        text.parse::<f64>().map_err(|_| {
+            self.pos = start;
+            self.error("invalid coefficient")
+        })
+    }
+
+    fn term(&mut self, sign: f64) -> Result<PauliString, QrackError> {
+        self.skip_ws();
+        let mut coefficient = sign;
+        let has_number = self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.');
+        if has_number {
+            coefficient *= self.number()?;
+        }
+        let mut paulis = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.peek() == Some('*') && (has_number || !paulis.is_empty()) {
+                self.pos += 1;
+                self.skip_ws();
+            }
+            let op = match self.peek().and_then(pauli_from_char) {
+                Some(op) => op,
+                None => break
+            };
+            self.pos += 1;
+            let start = self.pos;
+            if self.digits() == 0 {
+                return Err(self.error("expected qubit index"));
+            }
+            let q = self.slice(start).parse::<u64>().map_err(|_| {
+                self.pos = start;
+                self.error("qubit index out of range")
+            })?;
+            paulis.push((q, op));
+        }
+        if !has_number && paulis.is_empty() {
+            return Err(self.error("expected coefficient or Pauli operator"));
+        }
+        PauliString::new(coefficient, paulis)
+    }
+}
+
+fn pauli_from_char(c: char) -> Option<Pauli> {
+    match c {
+        'I' | 'i' => Some(Pauli::PauliI),
+        'X' | 'x' => Some(Pauli::PauliX),
+        'Y' | 'y' => Some(Pauli::PauliY),
+        'Z' | 'z' => Some(Pauli::PauliZ),
+        _ => None
+    }
+}
+
+fn pauli_char(p: Pauli) -> char {
+    match p {
+        Pauli::PauliI => 'I',
+        Pauli::PauliX => 'X',
+        Pauli::PauliY => 'Y',
+        Pauli::PauliZ => 'Z'
+    }
+}
@@ -0,0 +1,1002 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+// OpenQASM 2.0 import, and OpenQASM 3.0 export.
+//
+// A program is parsed, with custom "gate" definitions expanded, into a flat
+// list of instructions over global qubit and classical bit indices. Registers
+// are laid out in declaration order. The program can then either be recorded
+// into a QrackCircuit, (if it is purely unitary), or run on a QrackSimulator.
+//
+// All gates of "qelib1.inc" are available whether or not it is included.
+// Without the include, a program may define gates of the same names, which
+// then take precedence; with it, those names are reserved, as redefinitions.
+// Only "U" and "CX" are always reserved.
+//
+// Export, (see `QrackCircuit::to_qasm3()`), writes only the built-in "U" and
+// "gphase" gates, under "ctrl @" and "negctrl @" modifiers.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use gate_matrix;
+use pauli::Pauli;
+use circuit_instruction::CircuitInstruction;
+use qrack_circuit::QrackCircuit;
+use qrack_error::QrackError;
+use qrack_simulator::QrackSimulator;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QasmError {
+    // OpenQASM parse or semantic error
+    //
+    // Attributes:
+    //     line(usize): 1-based source line
+    //     column(usize): 1-based source column
+    //     message(String): description
+    pub line: usize,
+    pub column: usize,
+    pub message: String
+}
+
+impl fmt::Display for QasmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for QasmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QasmInstruction {
+    // Gate with all controls required to be `|1>`
+    Gate { controls: Vec<u64>, matrix: [f64;8], target: u64 },
+    // Swap with all controls required to be `|1>`
+    Swap { controls: Vec<u64>, qubit1: u64, qubit2: u64 },
+    Measure { qubit: u64, clbit: u64, line: usize, column: usize },
+    Reset { qubit: u64, line: usize, column: usize },
+    // Body runs if the classical register, read as an integer, equals "value".
+    Conditional { offset: u64, size: u64, value: u64, body: Vec<QasmInstruction>, line: usize, column: usize }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QasmRegister {
+    // Attributes:
+    //     name(String): register name
+    //     offset(u64): global index of element 0
+    //     size(u64): element count
+    pub name: String,
+    pub offset: u64,
+    pub size: u64
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QasmProgram {
+    // Parsed OpenQASM 2.0 program
+    //
+    // Attributes:
+    //     qregs(Vec<QasmRegister>): quantum registers, in declaration order
+    //     cregs(Vec<QasmRegister>): classical registers, in declaration order
+    //     instructions(Vec<QasmInstruction>): flattened program body
+    qregs: Vec<QasmRegister>,
+    cregs: Vec<QasmRegister>,
+    instructions: Vec<QasmInstruction>
+}
+
+pub fn parse(source: &str) -> Result<QasmProgram, QrackError> {
+    // Parse OpenQASM 2.0 source
+    //
+    // Raises:
+    //     Qasm: parse error, with line and column.
+    let mut p = Parser::new(source)?;
+    p.load_prelude()?;
+    p.program()?;
+    Ok(p.out)
+}
+
+pub fn parse_file<P: AsRef<Path>>(path: P) -> Result<QasmProgram, QrackError> {
+    // Parse an OpenQASM 2.0 file
+    //
+    // Raises:
+    //     Io: file can't be read.
+    //     Qasm: parse error, with line and column.
+    parse(&fs::read_to_string(path)?)
+}
+
+impl QasmProgram {
+    pub fn qregs(&self) -> &[QasmRegister] {
+        &self.qregs
+    }
+
+    pub fn cregs(&self) -> &[QasmRegister] {
+        &self.cregs
+    }
+
+    pub fn instructions(&self) -> &[QasmInstruction] {
+        &self.instructions
+    }
+
+    pub fn qubit_count(&self) -> u64 {
+        self.qregs.iter().map(|r| r.size).sum()
+    }
+
+    pub fn clbit_count(&self) -> u64 {
+        self.cregs.iter().map(|r| r.size).sum()
+    }
+
+    pub fn to_circuit(&self) -> Result<QrackCircuit, QrackError> {
+        // Record the program into a new QrackCircuit
+        //
+        // Raises:
+        //     Qasm: the program measures, resets or branches, which
+        //         QrackCircuit can't record.
+        let circ = QrackCircuit::new();
+        for i in &self.instructions {
+            match i {
+                QasmInstruction::Gate{ controls, matrix, target } => {
+                    if controls.is_empty() {
+                        circ.mtrx(matrix, *target);
+                    } else {
+                        circ.mcmtrx(controls.clone(), matrix, *target);
+                    }
+                },
+                QasmInstruction::Swap{ controls, qubit1, qubit2 } => {
+                    if controls.is_empty() {
+                        circ.swap(*qubit1, *qubit2);
+                    } else {
+                        circ.cswap(controls.clone(), *qubit1, *qubit2);
+                    }
+                },
+                QasmInstruction::Measure{ line, column, .. }
+                | QasmInstruction::Reset{ line, column, .. }
+                | QasmInstruction::Conditional{ line, column, .. } => {
+                    return Err(QrackError::Qasm(QasmError{
+                        line: *line,
+                        column: *column,
+                        message: "QrackCircuit can only record unitary gates".to_string()
+                    }));
+                }
+            }
+        }
+        Ok(circ)
+    }
+
+    pub fn run(&self, qsim: &QrackSimulator) -> Result<Vec<bool>, QrackError> {
+        // Run the program directly on a simulator
+        //
+        // Args:
+        //     qsim(&QrackSimulator): simulator with at least `qubit_count()` qubits
+        //
+        // Raises:
+        //     QubitOutOfRange: simulator has too few qubits.
+        //     Native: Qrack reported an error code.
+        //
+        // Returns:
+        //     classical bits, `clbit_count()` long, in register declaration order
+        let count = qsim.num_qubits()?;
+        if self.qubit_count() > count {
+            return Err(QrackError::QubitOutOfRange{ qubit: self.qubit_count() - 1, count });
+        }
+        let mut bits = vec![false; self.clbit_count() as usize];
+        run_instructions(&self.instructions, qsim, &mut bits)?;
+        Ok(bits)
+    }
+}
+
+fn run_instructions(instructions: &[QasmInstruction], qsim: &QrackSimulator, bits: &mut [bool]) -> Result<(), QrackError> {
+    for i in instructions {
+        match i {
+            QasmInstruction::Gate{ controls, matrix, target } => {
+                if controls.is_empty() {
+                    qsim.mtrx(matrix, *target)?;
+                } else {
+                    qsim.mcmtrx(controls.clone(), matrix, *target)?;
+                }
+            },
+            QasmInstruction::Swap{ controls, qubit1, qubit2 } => {
+                if controls.is_empty() {
+                    qsim.swap(*qubit1, *qubit2)?;
+                } else {
+                    qsim.cswap(controls.clone(), *qubit1, *qubit2)?;
+                }
+            },
+            QasmInstruction::Measure{ qubit, clbit, .. } => {
+                bits[*clbit as usize] = qsim.m(*qubit)? != 0;
+            },
+            QasmInstruction::Reset{ qubit, .. } => {
+                if qsim.m(*qubit)? != 0 {
+                    qsim.x(*qubit)?;
+                }
+            },
+            QasmInstruction::Conditional{ offset, size, value, body, .. } => {
+                let reg = &bits[(*offset as usize)..((offset + size) as usize)];
+                let v = reg.iter().rev().fold(0u64, |a, b| (a << 1) | (*b as u64));
+                if v == *value {
+                    run_instructions(body, qsim, bits)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn to_qasm3(qubits: u64, gates: &[CircuitInstruction]) -> String {
+    let mut out = String::from("OPENQASM 3.0;\n");
+    if qubits > 0 {
+        let _ = writeln!(out, "qubit[{}] q;", qubits);
+    }
+    for g in gates {
+        for (perm, m) in &g.payloads {
+            let mut modifiers = String::new();
+            let mut args = String::new();
+            for (i, c) in g.controls.iter().enumerate() {
+                modifiers += if (perm >> i) & 1 == 1 { "ctrl @ " } else { "negctrl @ " };
+                let _ = write!(args, "q[{}], ", c);
+            }
+            let (th, ph, la, gamma) = gate_matrix::zyz(m);
+            let _ = writeln!(out, "{}U({}, {}, {}) {}q[{}];", modifiers, th, ph, la, args, g.target);
+            if gamma != 0.0 {
+                // Under controls, the phase is relative, so it needs the control qubits.
+                if g.controls.is_empty() {
+                    let _ = writeln!(out, "gphase({});", gamma);
+                } else {
+                    let _ = writeln!(out, "{}gphase({}) {};", modifiers, gamma, args.trim_end_matches(", "));
+                }
+            }
+        }
+    }
+    out
+}
+
+// qelib1.inc gates without a single native matrix form, as their standard
+// definitions.
+const PRELUDE: &str = "
+gate rxx(theta) a, b { u3(pi/2, theta, 0) a; h b; cx a, b; u1(-theta) b; cx a, b; h b; u2(-pi, pi-theta) a; }
+gate rzz(theta) a, b { cx a, b; u1(theta) b; cx a, b; }
+gate rccx a, b, c { u2(0, pi) c; u1(pi/4) c; cx b, c; u1(-pi/4) c; cx a, c; u1(pi/4) c; cx b, c; u1(-pi/4) c; u2(0, pi) c; }
+gate rc3x a, b, c, d {
+    u2(0, pi) d; u1(pi/4) d; cx c, d; u1(-pi/4) d; u2(0, pi) d;
+    cx a, d; u1(pi/4) d; cx b, d; u1(-pi/4) d; cx a, d; u1(pi/4) d; cx b, d; u1(-pi/4) d;
+    u2(0, pi) d; u1(pi/4) d; cx c, d; u1(-pi/4) d; u2(0, pi) d;
+}
+";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Int(u64),
+    Real(f64),
+    Str(String),
+    Sym(&'static str),
+    Eof
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    line: usize,
+    column: usize
+}
+
+fn lex(source: &str) -> Result<Vec<Token>, QrackError> {
+    const SYMBOLS: [&str; 15] = ["->", "==", ";", ",", "(", ")", "[", "]", "{", "}", "+", "-", "*", "/", "^"];
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut i, mut line, mut column) = (0, 1, 1);
+    let err = |line, column, message: String| QrackError::Qasm(QasmError{ line, column, message });
+    while i < chars.len() {
+        let c = chars[i];
+        let (start, start_line, start_column) = (i, line, column);
+        if c == '\n' {
+            i += 1;
+            line += 1;
+            column = 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            column += 1;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        let tok = if c.is_ascii_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Tok::Ident(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let mut real = false;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            if i < chars.len() && chars[i] == '.' {
+                real = true;
+                i += 1;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                real = true;
+                i += 1;
+                if i < chars.len() && (chars[i] == '+' || chars[i] == '-') {
+                    i += 1;
+                }
+                let digits = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                if digits == i {
+                    return Err(err(line, column + (i - start), "expected exponent digits".to_string()));
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            if real {
+                Tok::Real(text.parse().map_err(|_| err(line, column, format!("invalid number \"{}\"", text)))?)
+            } else {
+                Tok::Int(text.parse().map_err(|_| err(line, column, format!("integer \"{}\" is too large", text)))?)
+            }
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' && chars[i] != '\n' {
+                i += 1;
+            }
+            if i >= chars.len() || chars[i] != '"' {
+                return Err(err(line, column, "unterminated string".to_string()));
+            }
+            i += 1;
+            Tok::Str(chars[(start + 1)..(i - 1)].iter().collect())
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                Some(s) => {
+                    i += s.len();
+                    Tok::Sym(s)
+                },
+                None => return Err(err(line, column, format!("unexpected character '{}'", c)))
+            }
+        };
+        column += i - start;
+        tokens.push(Token{ tok, line: start_line, column: start_column });
+    }
+    tokens.push(Token{ tok: Tok::Eof, line, column });
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f64),
+    Param(usize),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+    Call(fn(f64) -> f64, Box<Expr>)
+}
+
+impl Expr {
+    fn eval(&self, params: &[f64]) -> f64 {
+        match self {
+            Expr::Num(v) => *v,
+            Expr::Param(i) => params[*i],
+            Expr::Neg(e) => -e.eval(params),
+            Expr::Bin(op, a, b) => {
+                let (a, b) = (a.eval(params), b.eval(params));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b)
+                }
+            },
+            Expr::Call(f, e) => f(e.eval(params))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GateCall {
+    // Gate application inside a "gate" body, over the body's argument indices
+    name: String,
+    params: Vec<Expr>,
+    args: Vec<usize>,
+    line: usize,
+    column: usize
+}
+
+#[derive(Debug, Clone)]
+struct GateDef {
+    params: usize,
+    qubits: usize,
+    body: Option<Vec<GateCall>>
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // Gates defined by the program
+    gates: HashMap<String, GateDef>,
+    // qelib1.inc gates defined in PRELUDE
+    library: HashMap<String, GateDef>,
+    // Whether "qelib1.inc" was included
+    qelib1: bool,
+    out: QasmProgram
+}
+
+#[derive(Debug, Clone)]
+enum Arg {
+    // Resolved argument: one qubit or clbit, or a whole register
+    Bit(u64),
+    Reg(u64, u64)
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, QrackError> {
+        Ok(Self{
+            tokens: lex(source)?,
+            pos: 0,
+            gates: HashMap::new(),
+            library: HashMap::new(),
+            qelib1: false,
+            out: QasmProgram::default()
+        })
+    }
+
+    fn load_prelude(&mut self) -> Result<(), QrackError> {
+        let mut prelude = Parser::new(PRELUDE)?;
+        while prelude.peek() != &Tok::Eof {
+            prelude.gate_decl()?;
+        }
+        self.library = prelude.gates;
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str, library_only: bool) -> Option<(&GateDef, bool)> {
+        // Defined gate "name", and whether it's from the library; program
+        // definitions come first, except inside library gates
+        match self.gates.get(name) {
+            Some(def) if !library_only => Some((def, false)),
+            _ => self.library.get(name).map(|def| (def, true))
+        }
+    }
+
+    fn is_library(&self, name: &str) -> bool {
+        self.library.contains_key(name) || builtin_arity(name).is_some()
+    }
+
+    fn peek(&self) -> &Tok {
+        &self.tokens[self.pos].tok
+    }
+
+    fn here(&self) -> (usize, usize) {
+        (self.tokens[self.pos].line, self.tokens[self.pos].column)
+    }
+
+    fn error_at<T>(&self, (line, column): (usize, usize), message: String) -> Result<T, QrackError> {
+        Err(QrackError::Qasm(QasmError{ line, column, message }))
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, QrackError> {
+        self.error_at(self.here(), message)
+    }
+
+    fn describe(&self) -> String {
+        match self.peek() {
+            Tok::Ident(s) => format!("\"{}\"", s),
+            Tok::Int(v) => format!("{}", v),
+            Tok::Real(v) => format!("{}", v),
+            Tok::Str(s) => format!("\"{}\"", s),
+            Tok::Sym(s) => format!("'{}'", s),
+            Tok::Eof => "end of input".to_string()
+        }
+    }
+
+    fn eat(&mut self, sym: &str) -> bool {
+        if let Tok::Sym(s) = self.peek() {
+            if *s == sym {
+                self.pos += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn expect(&mut self, sym: &str) -> Result<(), QrackError> {
+        if self.eat(sym) {
+            return Ok(());
+        }
+        self.error(format!("expected '{}', found {}", sym, self.describe()))
+    }
+
+    fn ident(&mut self) -> Result<String, QrackError> {
+        if let Tok::Ident(s) = self.peek() {
+            let s = s.clone();
+            self.pos += 1;
+            return Ok(s);
+        }
+        self.error(format!("expected identifier, found {}", self.describe()))
+    }
+
+    fn int(&mut self) -> Result<u64, QrackError> {
+        if let Tok::Int(v) = self.peek() {
+            let v = *v;
+            self.pos += 1;
+            return Ok(v);
+        }
+        self.error(format!("expected integer, found {}", self.describe()))
+    }
+
+    fn keyword(&self, k: &str) -> bool {
+        matches!(self.peek(), Tok::Ident(s) if s == k)
+    }
+
+    fn program(&mut self) -> Result<(), QrackError> {
+        if self.keyword("OPENQASM") {
+            self.pos += 1;
+            let at = self.here();
+            let major = match self.peek().clone() {
+                Tok::Real(v) => v.trunc() as u64,
+                Tok::Int(v) => v,
+                _ => return self.error(format!("expected version, found {}", self.describe()))
+            };
+            self.pos += 1;
+            if major != 2 {
+                return self.error_at(at, format!("unsupported OpenQASM version {}, expected 2.0", major));
+            }
+            self.expect(";")?;
+        }
+        while self.peek() != &Tok::Eof {
+            self.statement()?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), QrackError> {
+        let at = self.here();
+        let word = self.ident()?;
+        match word.as_str() {
+            "include" => {
+                let at = self.here();
+                match self.peek().clone() {
+                    Tok::Str(ref f) if f == "qelib1.inc" => {
+                        let defined = self.gates.keys().filter(|n| self.is_library(n)).min().cloned();
+                        if let Some(name) = defined {
+                            return self.error_at(at, format!("gate \"{}\" is already defined, so \"qelib1.inc\" can't be included", name));
+                        }
+                        self.qelib1 = true;
+                        self.pos += 1;
+                    },
+                    Tok::Str(f) => return self.error_at(at, format!("can't include \"{}\", only \"qelib1.inc\" is available", f)),
+                    _ => return self.error(format!("expected file name, found {}", self.describe()))
+                }
+                self.expect(";")
+            },
+            "qreg" | "creg" => {
+                let name_at = self.here();
+                let name = self.ident()?;
+                self.expect("[")?;
+                let size = self.int()?;
+                self.expect("]")?;
+                self.expect(";")?;
+                if self.register(&name, true).is_some() || self.register(&name, false).is_some() {
+                    return self.error_at(name_at, format!("register \"{}\" is already declared", name));
+                }
+                let regs = if word == "qreg" { &mut self.out.qregs } else { &mut self.out.cregs };
+                let offset = regs.iter().map(|r| r.size).sum();
+                regs.push(QasmRegister{ name, offset, size });
+                Ok(())
+            },
+            "gate" | "opaque" => {
+                self.pos -= 1;
+                self.gate_decl()
+            },
+            "barrier" => {
+                self.arg_list(true)?;
+                self.expect(";")
+            },
+            "if" => {
+                self.expect("(")?;
+                let reg_at = self.here();
+                let name = self.ident()?;
+                let reg = match self.register(&name, false) {
+                    Some(r) => r.clone(),
+                    None => return self.error_at(reg_at, format!("undeclared classical register \"{}\"", name))
+                };
+                self.expect("==")?;
+                let value = self.int()?;
+                self.expect(")")?;
+                let mut body = Vec::new();
+                let op_at = self.here();
+                let op = self.ident()?;
+                self.qop(&op, op_at, &mut body)?;
+                self.out.instructions.push(QasmInstruction::Conditional{
+                    offset: reg.offset, size: reg.size, value, body, line: at.0, column: at.1
+                });
+                Ok(())
+            },
+            _ => {
+                let mut out = Vec::new();
+                self.qop(&word, at, &mut out)?;
+                self.out.instructions.append(&mut out);
+                Ok(())
+            }
+        }
+    }
+
+    fn register(&self, name: &str, quantum: bool) -> Option<&QasmRegister> {
+        let regs = if quantum { &self.out.qregs } else { &self.out.cregs };
+        regs.iter().find(|r| r.name == name)
+    }
+
+    fn arg(&mut self, quantum: bool) -> Result<Arg, QrackError> {
+        let at = self.here();
+        let name = self.ident()?;
+        let reg = match self.register(&name, quantum) {
+            Some(r) => r.clone(),
+            None => return self.error_at(at, format!("undeclared {} register \"{}\"", if quantum { "quantum" } else { "classical" }, name))
+        };
+        if !self.eat("[") {
+            return Ok(Arg::Reg(reg.offset, reg.size));
+        }
+        let index_at = self.here();
+        let index = self.int()?;
+        self.expect("]")?;
+        if index >= reg.size {
+            return self.error_at(index_at, format!("index {} is out of range for register \"{}\" of size {}", index, name, reg.size));
+        }
+        Ok(Arg::Bit(reg.offset + index))
+    }
+
+    fn arg_list(&mut self, quantum: bool) -> Result<Vec<Arg>, QrackError> {
+        let mut args = vec![self.arg(quantum)?];
+        while self.eat(",") {
+            args.push(self.arg(quantum)?);
+        }
+        Ok(args)
+    }
+
+    fn broadcast(&self, at: (usize, usize), args: &[Arg]) -> Result<Vec<Vec<u64>>, QrackError> {
+        // Expand register arguments elementwise, (single bits repeat)
+        let mut width = None;
+        for a in args {
+            if let Arg::Reg(_, size) = a {
+                if width.is_some_and(|w| w != *size) {
+                    return self.error_at(at, "register arguments have different sizes".to_string());
+                }
+                width = Some(*size);
+            }
+        }
+        let rows = (0..width.unwrap_or(1)).map(|i| {
+            args.iter().map(|a| match a {
+                Arg::Bit(b) => *b,
+                Arg::Reg(offset, _) => offset + i
+            }).collect::<Vec<u64>>()
+        }).collect::<Vec<Vec<u64>>>();
+        for row in &rows {
+            for (j, q) in row.iter().enumerate() {
+                if row[..j].contains(q) {
+                    return self.error_at(at, format!("qubit {} is used more than once", q));
+                }
+            }
+        }
+        Ok(rows)
+    }
+
+    fn qop(&mut self, name: &str, at: (usize, usize), out: &mut Vec<QasmInstruction>) -> Result<(), QrackError> {
+        match name {
+            "measure" => {
+                let q = self.arg(true)?;
+                self.expect("->")?;
+                let c = self.arg(false)?;
+                self.expect(";")?;
+                let (qs, cs) = match (q, c) {
+                    (Arg::Bit(q), Arg::Bit(c)) => (vec![q], vec![c]),
+                    (Arg::Reg(qo, qn), Arg::Reg(co, cn)) if qn == cn => ((qo..(qo + qn)).collect(), (co..(co + cn)).collect()),
+                    _ => return self.error_at(at, "measure arguments have different sizes".to_string())
+                };
+                for (qubit, clbit) in qs.into_iter().zip(cs) {
+                    out.push(QasmInstruction::Measure{ qubit, clbit, line: at.0, column: at.1 });
+                }
+                Ok(())
+            },
+            "reset" => {
+                let q = self.arg(true)?;
+                self.expect(";")?;
+                for qubit in self.broadcast(at, &[q])? {
+                    out.push(QasmInstruction::Reset{ qubit: qubit[0], line: at.0, column: at.1 });
+                }
+                Ok(())
+            },
+            _ => {
+                let params = if self.eat("(") {
+                    let mut params = Vec::new();
+                    if !self.eat(")") {
+                        loop {
+                            params.push(self.expr(&[])?.eval(&[]));
+                            if self.eat(")") {
+                                break;
+                            }
+                            self.expect(",")?;
+                        }
+                    }
+                    params
+                } else {
+                    Vec::new()
+                };
+                let args = self.arg_list(true)?;
+                self.expect(";")?;
+                for qubits in self.broadcast(at, &args)? {
+                    self.apply(name, &params, &qubits, at, false, out)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn gate_decl(&mut self) -> Result<(), QrackError> {
+        let opaque = self.ident()? == "opaque";
+        let name_at = self.here();
+        let name = self.ident()?;
+        let reserved = name == "U" || name == "CX" || (self.qelib1 && self.is_library(&name));
+        if self.gates.contains_key(&name) || reserved {
+            return self.error_at(name_at, format!("gate \"{}\" is already defined", name));
+        }
+        let mut params = Vec::new();
+        if self.eat("(") && !self.eat(")") {
+            loop {
+                params.push(self.ident()?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+        let mut qargs = vec![self.ident()?];
+        while self.eat(",") {
+            qargs.push(self.ident()?);
+        }
+        if opaque {
+            self.expect(";")?;
+            self.gates.insert(name, GateDef{ params: params.len(), qubits: qargs.len(), body: None });
+            return Ok(());
+        }
+        self.expect("{")?;
+        let mut body = Vec::new();
+        while !self.eat("}") {
+            let (line, column) = self.here();
+            let op = self.ident()?;
+            if op == "barrier" {
+                while !self.eat(";") {
+                    self.ident()?;
+                    self.eat(",");
+                }
+                continue;
+            }
+            let (arity, nparams) = match self.lookup(&op, false) {
+                Some((g, _)) => (g.qubits, g.params),
+                None => match builtin_arity(&op) {
+                    Some(a) => (a.1, a.0),
+                    None => return self.error_at((line, column), format!("undefined gate \"{}\"", op))
+                }
+            };
+            let mut exprs = Vec::new();
+            if self.eat("(") && !self.eat(")") {
+                loop {
+                    exprs.push(self.expr(&params)?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+            let mut args = Vec::new();
+            loop {
+                let arg_at = self.here();
+                let a = self.ident()?;
+                match qargs.iter().position(|q| *q == a) {
+                    Some(i) if !args.contains(&i) => args.push(i),
+                    Some(_) => return self.error_at(arg_at, format!("qubit argument \"{}\" is used more than once", a)),
+                    None => return self.error_at(arg_at, format!("unknown qubit argument \"{}\"", a))
+                }
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            self.expect(";")?;
+            if exprs.len() != nparams || args.len() != arity {
+                return self.error_at((line, column), format!(
+                    "gate \"{}\" takes {} parameter(s) and {} qubit(s), found {} and {}", op, nparams, arity, exprs.len(), args.len()));
+            }
+            body.push(GateCall{ name: op, params: exprs, args, line, column });
+        }
+        self.gates.insert(name, GateDef{ params: params.len(), qubits: qargs.len(), body: Some(body) });
+        Ok(())
+    }
+
+    fn apply(&self, name: &str, params: &[f64], qubits: &[u64], at: (usize, usize), library_only: bool, out: &mut Vec<QasmInstruction>) -> Result<(), QrackError> {
+        let def = self.lookup(name, library_only);
+        let (nparams, arity) = match def {
+            Some((g, _)) => (g.params, g.qubits),
+            None => match builtin_arity(name) {
+                Some(a) => a,
+                None => return self.error_at(at, format!("undefined gate \"{}\"", name))
+            }
+        };
+        if params.len() != nparams || qubits.len() != arity {
+            return self.error_at(at, format!(
+                "gate \"{}\" takes {} parameter(s) and {} qubit(s), found {} and {}", name, nparams, arity, params.len(), qubits.len()));
+        }
+        let (def, in_library) = match def {
+            Some(def) => def,
+            None => {
+                lower_builtin(name, params, qubits, out);
+                return Ok(());
+            }
+        };
+        let body = match def.body {
+            Some(ref body) => body,
+            None => return self.error_at(at, format!("opaque gate \"{}\" has no definition", name))
+        };
+        for call in body {
+            let p: Vec<f64> = call.params.iter().map(|e| e.eval(params)).collect();
+            let q: Vec<u64> = call.args.iter().map(|i| qubits[*i]).collect();
+            self.apply(&call.name, &p, &q, (call.line, call.column), in_library, out)?;
+        }
+        Ok(())
+    }
+
+    // expression := term (("+" | "-") term)*
+    fn expr(&mut self, params: &[String]) -> Result<Expr, QrackError> {
+        let mut e = self.term(params)?;
+        loop {
+            let op = if self.eat("+") { '+' } else if self.eat("-") { '-' } else { return Ok(e) };
+            e = Expr::Bin(op, Box::new(e), Box::new(self.term(params)?));
+        }
+    }
+
+    // term := factor (("*" | "/") factor)*
+    fn term(&mut self, params: &[String]) -> Result<Expr, QrackError> {
+        let mut e = self.factor(params)?;
+        loop {
+            let op = if self.eat("*") { '*' } else if self.eat("/") { '/' } else { return Ok(e) };
+            e = Expr::Bin(op, Box::new(e), Box::new(self.factor(params)?));
+        }
+    }
+
+    // factor := "-" factor | atom ["^" factor]
+    fn factor(&mut self, params: &[String]) -> Result<Expr, QrackError> {
+        if self.eat("-") {
+            return Ok(Expr::Neg(Box::new(self.factor(params)?)));
+        }
+        let base = self.atom(params)?;
+        if self.eat("^") {
+            return Ok(Expr::Bin('^', Box::new(base), Box::new(self.factor(params)?)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self, params: &[String]) -> Result<Expr, QrackError> {
+        let at = self.here();
+        match self.peek().clone() {
+            Tok::Int(v) => {
+                self.pos += 1;
+                Ok(Expr::Num(v as f64))
+            },
+            Tok::Real(v) => {
+                self.pos += 1;
+                Ok(Expr::Num(v))
+            },
+            Tok::Sym("(") => {
+                self.pos += 1;
+                let e = self.expr(params)?;
+                self.expect(")")?;
+                Ok(e)
+            },
+            Tok::Ident(name) => {
+                self.pos += 1;
+                if name == "pi" {
+                    return Ok(Expr::Num(PI));
+                }
+                if let Some(i) = params.iter().position(|p| *p == name) {
+                    return Ok(Expr::Param(i));
+                }
+                let f: fn(f64) -> f64 = match name.as_str() {
+                    "sin" => f64::sin,
+                    "cos" => f64::cos,
+                    "tan" => f64::tan,
+                    "exp" => f64::exp,
+                    "ln" => f64::ln,
+                    "sqrt" => f64::sqrt,
+                    _ => return self.error_at(at, format!("unknown identifier \"{}\" in expression", name))
+                };
+                self.expect("(")?;
+                let e = self.expr(params)?;
+                self.expect(")")?;
+                Ok(Expr::Call(f, Box::new(e)))
+            },
+            _ => self.error(format!("expected expression, found {}", self.describe()))
+        }
+    }
+}
+
+fn builtin_arity(name: &str) -> Option<(usize, usize)> {
+    // (parameter count, qubit count) of natively lowered gates
+    Some(match name {
+        "id" | "x" | "y" | "z" | "h" | "s" | "sdg" | "t" | "tdg" | "sx" | "sxdg" => (0, 1),
+        "u0" | "u1" | "p" | "rx" | "ry" | "rz" => (1, 1),
+        "u2" => (2, 1),
+        "U" | "u3" | "u" => (3, 1),
+        "CX" | "cx" | "cy" | "cz" | "ch" | "csx" | "swap" => (0, 2),
+        "crx" | "cry" | "crz" | "cu1" | "cp" => (1, 2),
+        "cu3" => (3, 2),
+        "cu" => (4, 2),
+        "ccx" | "cswap" => (0, 3),
+        "c3x" | "c3sqrtx" => (0, 4),
+        "c4x" => (0, 5),
+        _ => return None
+    })
+}
+
+fn lower_builtin(name: &str, p: &[f64], q: &[u64], out: &mut Vec<QasmInstruction>) {
+    let m = match name {
+        "id" | "u0" => return,
+        "x" => gate_matrix::X,
+        "y" => gate_matrix::Y,
+        "z" => gate_matrix::Z,
+        "h" => gate_matrix::H,
+        "s" => gate_matrix::S,
+        "sdg" => gate_matrix::ADJ_S,
+        "t" => gate_matrix::T,
+        "tdg" => gate_matrix::ADJ_T,
+        "sx" => gate_matrix::SX,
+        "sxdg" => gate_matrix::SXDG,
+        "u1" | "p" => gate_matrix::phase(p[0]),
+        "rx" => gate_matrix::r(Pauli::PauliX, p[0]),
+        "ry" => gate_matrix::r(Pauli::PauliY, p[0]),
+        "rz" => gate_matrix::r(Pauli::PauliZ, p[0]),
+        "u2" => gate_matrix::u(PI / 2.0, p[0], p[1]),
+        "U" | "u3" | "u" => gate_matrix::u(p[0], p[1], p[2]),
+        "CX" | "cx" | "ccx" | "c3x" | "c4x" => gate_matrix::X,
+        "cy" => gate_matrix::Y,
+        "cz" => gate_matrix::Z,
+        "ch" => gate_matrix::H,
+        "csx" | "c3sqrtx" => gate_matrix::SX,
+        "crx" => gate_matrix::r(Pauli::PauliX, p[0]),
+        "cry" => gate_matrix::r(Pauli::PauliY, p[0]),
+        "crz" => gate_matrix::r(Pauli::PauliZ, p[0]),
+        "cu1" | "cp" => gate_matrix::phase(p[0]),
+        "cu3" => gate_matrix::u(p[0], p[1], p[2]),
+        "cu" => {
+            // Controlled `e^{i*gamma} U(theta, phi, lambda)`
+            let u = gate_matrix::u(p[0], p[1], p[2]);
+            let (c, s) = (p[3].cos(), p[3].sin());
+            let mut m = [0.0; 8];
+            for k in 0..4 {
+                m[2 * k] = c * u[2 * k] - s * u[2 * k + 1];
+                m[2 * k + 1] = s * u[2 * k] + c * u[2 * k + 1];
+            }
+            m
+        },
+        "swap" => {
+            out.push(QasmInstruction::Swap{ controls: Vec::new(), qubit1: q[0], qubit2: q[1] });
+            return;
+        },
+        "cswap" => {
+            out.push(QasmInstruction::Swap{ controls: vec![q[0]], qubit1: q[1], qubit2: q[2] });
+            return;
+        },
+        _ => unreachable!("gate \"{}\" has an arity but no lowering", name)
+    };
+    let (controls, target) = q.split_at(q.len() - 1);
+    out.push(QasmInstruction::Gate{ controls: controls.to_vec(), matrix: m, target: target[0] });
+}
@@ -3,11 +3,37 @@
 // Use of this source code is governed by an MIT-style license that can be
 // found in the LICENSE file or at https://opensource.org/licenses/MIT.
 
-use std::ffi::CString;
+use std::collections::{BTreeSet, HashMap};
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::io::{Read, Write};
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use circuit_drawing;
+use circuit_instruction::{parse_circuit, CircuitInstruction};
+use circuit_options::CircuitOptions;
+use circuit_stats::CircuitStats;
+use gate_matrix;
+use matrix::Matrix;
+use num_complex::Complex;
+use pauli::Pauli;
+use qasm;
 use qrack_error::QrackError;
-use qrack_simulator::QrackSimulator;
+use qrack_simulator::{path_to_cstring, QrackSimulator};
 use qrack_system;
+use transpile::RandomStates;
+
+// Widest circuit for `to_unitary()`, and for exact `equivalent()` checks
+pub const MAX_UNITARY_QUBITS: u64 = 12;
+// Random input states per `equivalent()` Hadamard test of wider circuits
+const EQUIVALENCE_TRIALS: usize = 8;
 
 pub struct QrackCircuit {
     // Class that exposes the QNeuron class of Qrack
@@ -53,9 +79,15 @@ impl Drop for QrackCircuit {
 impl QrackCircuit {
     // constructors
     pub fn new() -> Self {
+        Self::with_options(CircuitOptions::default())
+    }
+
+    pub fn with_options(options: CircuitOptions) -> Self {
+        // Args:
+        //     options(CircuitOptions): Qrack construction options
         let cid;
         unsafe {
-            cid = qrack_system::init_qcircuit(false);
+            cid = qrack_system::init_qcircuit(options.collapse);
         }
         Self{cid}
     }
@@ -131,6 +163,432 @@ impl QrackCircuit {
         }
     }
 
+    // single-qubit gates
+
+    pub fn x(&self, q: u64) {
+        // Add X gate.
+        //
+        // Applies the Pauli “X” operator to the qubit at position “q.”
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.mtrx(&gate_matrix::X, q)
+    }
+
+    pub fn y(&self, q: u64) {
+        // Add Y gate.
+        //
+        // Applies the Pauli “Y” operator to the qubit at “q.”
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.mtrx(&gate_matrix::Y, q)
+    }
+
+    pub fn z(&self, q: u64) {
+        // Add Z gate.
+        //
+        // Applies the Pauli “Z” operator to the qubit at “q.”
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.mtrx(&gate_matrix::Z, q)
+    }
+
+    pub fn h(&self, q: u64) {
+        // Add H gate.
+        //
+        // Applies the Hadarmard operator to the qubit at “q.”
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.mtrx(&gate_matrix::H, q)
+    }
+
+    pub fn s(&self, q: u64) {
+        // Add S gate.
+        //
+        // Applies the 1/4 phase rotation to the qubit at “q.”
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.mtrx(&gate_matrix::S, q)
+    }
+
+    pub fn t(&self, q: u64) {
+        // Add T gate.
+        //
+        // Applies the 1/8 phase rotation to the qubit at “q.”
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.mtrx(&gate_matrix::T, q)
+    }
+
+    pub fn adjs(&self, q: u64) {
+        // Add AdjS gate.
+        //
+        // Applies the inverse of the S gate to the qubit at “q.”
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.mtrx(&gate_matrix::ADJ_S, q)
+    }
+
+    pub fn adjt(&self, q: u64) {
+        // Add AdjT gate.
+        //
+        // Applies the inverse of the T gate to the qubit at “q.”
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        self.mtrx(&gate_matrix::ADJ_T, q)
+    }
+
+    pub fn u(&self, q: u64, th: f64, ph: f64, la: f64) {
+        // Add general unitary gate.
+        //
+        // `U(theta, phi, lambda) = RZ(phi + pi/2)RX(theta)RZ(lambda - pi/2)`
+        //
+        // Args:
+        //     q(u64): the qubit number on which the gate is applied to.
+        //     th(f64): theta
+        //     ph(f64): phi
+        //     la(f64): lambda
+        self.mtrx(&gate_matrix::u(th, ph, la), q)
+    }
+
+    pub fn r(&self, b: Pauli, ph: f64, q: u64) {
+        // Add rotation gate.
+        //
+        // Rotate the qubit along the given pauli basis by the given angle.
+        //
+        // Args:
+        //     b(Pauli): Pauli basis
+        //     ph(f64): rotation angle
+        //     q(u64): the qubit number on which the gate is applied to
+        self.mtrx(&gate_matrix::r(b, ph), q)
+    }
+
+    // multi-qubit gates
+
+    pub fn mcx(&self, c: Vec<u64>, q: u64) {
+        // Add multi-controlled X gate
+        //
+        // If all controlled qubits are `|1>` then the X gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::X, q, false)
+    }
+
+    pub fn mcy(&self, c: Vec<u64>, q: u64) {
+        // Add multi-controlled Y gate
+        //
+        // If all controlled qubits are `|1>` then the Y gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::Y, q, false)
+    }
+
+    pub fn mcz(&self, c: Vec<u64>, q: u64) {
+        // Add multi-controlled Z gate
+        //
+        // If all controlled qubits are `|1>` then the Z gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::Z, q, false)
+    }
+
+    pub fn mch(&self, c: Vec<u64>, q: u64) {
+        // Add multi-controlled H gate
+        //
+        // If all controlled qubits are `|1>` then the H gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::H, q, false)
+    }
+
+    pub fn mcs(&self, c: Vec<u64>, q: u64) {
+        // Add multi-controlled S gate
+        //
+        // If all controlled qubits are `|1>` then the S gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::S, q, false)
+    }
+
+    pub fn mct(&self, c: Vec<u64>, q: u64) {
+        // Add multi-controlled T gate
+        //
+        // If all controlled qubits are `|1>` then the T gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::T, q, false)
+    }
+
+    pub fn mcadjs(&self, c: Vec<u64>, q: u64) {
+        // Add multi-controlled AdjS gate
+        //
+        // If all controlled qubits are `|1>` then the AdjS gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::ADJ_S, q, false)
+    }
+
+    pub fn mcadjt(&self, c: Vec<u64>, q: u64) {
+        // Add multi-controlled AdjT gate
+        //
+        // If all controlled qubits are `|1>` then the AdjT gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::ADJ_T, q, false)
+    }
+
+    pub fn mcu(&self, c: Vec<u64>, q: u64, th: f64, ph: f64, la: f64) {
+        // Add multi-controlled arbitrary unitary
+        //
+        // If all controlled qubits are `|1>` then the unitary gate described by
+        // parameters is applied to the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        //     th(f64): theta
+        //     ph(f64): phi
+        //     la(f64): lambda
+        self.append_controlled(&c, &gate_matrix::u(th, ph, la), q, false)
+    }
+
+    pub fn mcr(&self, b: Pauli, ph: f64, c: Vec<u64>, q: u64) {
+        // Add multi-controlled rotation gate
+        //
+        // If all controlled qubits are `|1>` then the target qubit is rotated
+        // along the given pauli basis by the given angle.
+        //
+        // Args:
+        //     b(Pauli): Pauli basis
+        //     ph(f64): rotation angle
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::r(b, ph), q, false)
+    }
+
+    pub fn mcmtrx(&self, c: Vec<u64>, m: &[f64;8], q: u64) {
+        // Add multi-controlled arbitrary operator
+        //
+        // If all controlled qubits are `|1>` then the arbitrary operation by
+        // parameters is applied to the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits
+        //     m(&[f64;8]): row-major complex list representing the operator.
+        //     q(u64): target qubit
+        self.append_controlled(&c, m, q, false)
+    }
+
+    pub fn macx(&self, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled X gate
+        //
+        // If all controlled qubits are `|0>` then the X gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::X, q, true)
+    }
+
+    pub fn macy(&self, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled Y gate
+        //
+        // If all controlled qubits are `|0>` then the Y gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::Y, q, true)
+    }
+
+    pub fn macz(&self, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled Z gate
+        //
+        // If all controlled qubits are `|0>` then the Z gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::Z, q, true)
+    }
+
+    pub fn mach(&self, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled H gate
+        //
+        // If all controlled qubits are `|0>` then the H gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::H, q, true)
+    }
+
+    pub fn macs(&self, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled S gate
+        //
+        // If all controlled qubits are `|0>` then the S gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::S, q, true)
+    }
+
+    pub fn mact(&self, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled T gate
+        //
+        // If all controlled qubits are `|0>` then the T gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::T, q, true)
+    }
+
+    pub fn macadjs(&self, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled AdjS gate
+        //
+        // If all controlled qubits are `|0>` then the AdjS gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::ADJ_S, q, true)
+    }
+
+    pub fn macadjt(&self, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled AdjT gate
+        //
+        // If all controlled qubits are `|0>` then the AdjT gate is applied to
+        // the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::ADJ_T, q, true)
+    }
+
+    pub fn macu(&self, c: Vec<u64>, q: u64, th: f64, ph: f64, la: f64) {
+        // Add anti multi-controlled arbitrary unitary
+        //
+        // If all controlled qubits are `|0>` then the unitary gate described by
+        // parameters is applied to the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        //     th(f64): theta
+        //     ph(f64): phi
+        //     la(f64): lambda
+        self.append_controlled(&c, &gate_matrix::u(th, ph, la), q, true)
+    }
+
+    pub fn macr(&self, b: Pauli, ph: f64, c: Vec<u64>, q: u64) {
+        // Add anti multi-controlled rotation gate
+        //
+        // If all controlled qubits are `|0>` then the target qubit is rotated
+        // along the given pauli basis by the given angle.
+        //
+        // Args:
+        //     b(Pauli): Pauli basis
+        //     ph(f64): rotation angle
+        //     c(Vec<u64>): list of controlled qubits.
+        //     q(u64): target qubit.
+        self.append_controlled(&c, &gate_matrix::r(b, ph), q, true)
+    }
+
+    pub fn macmtrx(&self, c: Vec<u64>, m: &[f64;8], q: u64) {
+        // Add anti multi-controlled arbitrary operator
+        //
+        // If all controlled qubits are `|0>` then the arbitrary operation by
+        // parameters is applied to the target qubit.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits
+        //     m(&[f64;8]): row-major complex list representing the operator.
+        //     q(u64): target qubit
+        self.append_controlled(&c, m, q, true)
+    }
+
+    pub fn cswap(&self, c: Vec<u64>, qi1: u64, qi2: u64) {
+        // Add controlled-swap gate
+        //
+        // Swaps the qubits at two given positions if all control qubits are `|1>`.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     qi1(u64): qubit index 1
+        //     qi2(u64): qubit index 2
+        self.append_cswap(c, qi1, qi2, false)
+    }
+
+    pub fn acswap(&self, c: Vec<u64>, qi1: u64, qi2: u64) {
+        // Add anti-controlled-swap gate
+        //
+        // Swaps the qubits at two given positions if all control qubits are `|0>`.
+        //
+        // Args:
+        //     c(Vec<u64>): list of controlled qubits.
+        //     qi1(u64): qubit index 1
+        //     qi2(u64): qubit index 2
+        self.append_cswap(c, qi1, qi2, true)
+    }
+
+    fn append_controlled(&self, c: &[u64], m: &[f64;8], q: u64, anti: bool) {
+        // All controls set, (or all clear, if "anti") selects the payload.
+        let p = if anti { 0 } else { all_ones(c.len()) };
+        self.ucmtrx(c.to_vec(), m, q, p)
+    }
+
+    fn append_cswap(&self, c: Vec<u64>, qi1: u64, qi2: u64, anti: bool) {
+        // `SWAP = CX(2->1) CX(1->2) CX(2->1)`, and only the middle CNOT needs
+        // the extra controls.
+        let mut _c = c.to_vec();
+        _c.push(qi1);
+        let p = if anti { 1 << c.len() } else { all_ones(_c.len()) };
+        self.ucmtrx(vec![qi2], &gate_matrix::X, qi1, 1);
+        self.ucmtrx(_c, &gate_matrix::X, qi2, p);
+        self.ucmtrx(vec![qi2], &gate_matrix::X, qi1, 1);
+    }
+
     pub fn run(&self, qsim: &QrackSimulator) -> Result<(), QrackError> {
         // Run circuit on simulator
         //
@@ -147,29 +605,415 @@ impl QrackCircuit {
         qsim.check_error()
     }
 
-    pub fn out_to_file(&self, filename: &str) -> () {
+    pub fn out_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), QrackError> {
         // Output optimized circuit to file
         //
         // Outputs the (optimized) circuit to a file named
-        // according to the "filename" parameter.
+        // according to the "path" parameter.
         //
         // Args:
-        //     filename: Name of file
+        //     path(AsRef<Path>): name of file
+        //
+        // Raises:
+        //     Io: file can't be written.
+        let path = path.as_ref();
+        let mut filename = path_to_cstring(path)?.into_bytes_with_nul();
+        // The C++ library doesn't report stream failures, so open here first.
+        File::create(path)?;
         unsafe {
-            qrack_system::qcircuit_out_to_file(self.cid, CString::new(filename).unwrap().into_bytes_with_nul().as_mut_ptr() as *mut i8)
+            qrack_system::qcircuit_out_to_file(self.cid, filename.as_mut_ptr() as *mut c_char)
+        }
+        if fs::metadata(path)?.len() == 0 {
+            return Err(QrackError::Io(io::Error::other("no circuit data was written")));
         }
+        Ok(())
     }
 
-    pub fn in_from_file(&self, filename: &str) -> () {
+    pub fn in_from_file<P: AsRef<Path>>(&self, path: P) -> Result<(), QrackError> {
         // Read in optimized circuit from file
         //
         // Reads in an (optimized) circuit from a file named
-        // according to the "filename" parameter.
+        // according to the "path" parameter.
         //
         // Args:
-        //     filename: Name of file
+        //     path(AsRef<Path>): name of file
+        //
+        // Raises:
+        //     Io: file can't be read.
+        //     InvalidArgument: file isn't a valid circuit.
+        //     QubitOutOfRange: a gate addresses a qubit past the qubit count.
+        let path = path.as_ref();
+        let mut filename = path_to_cstring(path)?.into_bytes_with_nul();
+        // The C++ library doesn't validate its input, so parse here first.
+        parse_circuit(&fs::read_to_string(path)?)?;
         unsafe {
-            qrack_system::qcircuit_in_from_file(self.cid, CString::new(filename).unwrap().into_bytes_with_nul().as_mut_ptr() as *mut i8)
+            qrack_system::qcircuit_in_from_file(self.cid, filename.as_mut_ptr() as *mut c_char)
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, QrackError> {
+        // Serialize the (optimized) circuit, in the format of `out_to_file()`
+        //
+        // This is not in-memory serialization: the C API only writes circuits
+        // to files, so Qrack writes a file in a fresh temporary directory,
+        // (readable only by this user), which is read back and removed.
+        //
+        // Qrack writes about 6 significant digits per matrix element, so a
+        // round trip through `from_bytes()` only keeps gates to within
+        // `gate_matrix::TOLERANCE`.
+        //
+        // Raises:
+        //     Io: the circuit couldn't be read back from Qrack.
+        Ok(self.dump()?.into_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<QrackCircuit, QrackError> {
+        // Deserialize a circuit written by `to_bytes()` or `out_to_file()`
+        //
+        // The circuit is rebuilt in memory, gate by gate, with no file
+        // access. If no gate acts on the last qubit of the recorded qubit
+        // count, an identity gate is appended there, to keep the width.
+        // Matrix elements are only as precise as the text, (see `to_bytes()`).
+        //
+        // Raises:
+        //     InvalidArgument: "bytes" isn't a valid circuit.
+        //     QubitOutOfRange: a gate addresses a qubit past the qubit count.
+        let text = str::from_utf8(bytes)
+            .map_err(|_| QrackError::InvalidArgument("circuit data is not UTF-8".to_string()))?;
+        let (qubits, instructions) = parse_circuit(text)?;
+        let out = QrackCircuit::new();
+        for i in &instructions {
+            out.append_instruction(i);
+        }
+        if out.get_qubit_count() < qubits {
+            out.mtrx(&gate_matrix::I, qubits - 1);
+        }
+        Ok(out)
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), QrackError> {
+        // Write `to_bytes()` to "writer", (by way of a private temporary file)
+        //
+        // Raises:
+        //     Io: reading back from Qrack or writing failed.
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<QrackCircuit, QrackError> {
+        // Read all of "reader", then `from_bytes()`
+        //
+        // Raises:
+        //     Io: reading failed.
+        //     InvalidArgument: the data isn't a valid circuit.
+        //     QubitOutOfRange: a gate addresses a qubit past the qubit count.
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        QrackCircuit::from_bytes(&bytes)
+    }
+
+    pub fn instructions(&self) -> Result<Vec<CircuitInstruction>, QrackError> {
+        // Gates of the (optimized) circuit, in application order
+        //
+        // Raises:
+        //     Io: the circuit couldn't be read back from Qrack.
+        //     InvalidArgument: Qrack's output is malformed.
+        Ok(parse_circuit(&self.dump()?)?.1)
+    }
+
+    pub fn append_instruction(&self, i: &CircuitInstruction) {
+        // Append every payload of "i" as a uniformly controlled gate
+        for (p, m) in &i.payloads {
+            self.ucmtrx(i.controls.clone(), m, i.target, *p);
+        }
+    }
+
+    pub fn append(&self, other: &QrackCircuit, qubit_map: &[u64]) -> Result<(), QrackError> {
+        // Append the gates of "other", with its qubit "i" on "qubit_map[i]"
+        //
+        // Args:
+        //     other(&QrackCircuit): circuit to append
+        //     qubit_map(&[u64]): qubit of this circuit for each qubit of "other"
+        //
+        // Raises:
+        //     LengthMismatch: "qubit_map" is shorter than other's qubit count.
+        //     InvalidArgument: "qubit_map" repeats a qubit.
+        //     Io: "other" couldn't be read back from Qrack.
+        let width = other.get_qubit_count() as usize;
+        if qubit_map.len() < width {
+            return Err(QrackError::LengthMismatch{ expected: width, actual: qubit_map.len() });
+        }
+        if qubit_map.iter().collect::<BTreeSet<_>>().len() != qubit_map.len() {
+            return Err(QrackError::InvalidArgument(format!("qubit map {:?} repeats a qubit", qubit_map)));
+        }
+        for i in other.instructions()? {
+            self.append_instruction(&i.remapped(|q| qubit_map[q as usize])?);
+        }
+        Ok(())
+    }
+
+    pub fn tensor(&self, other: &QrackCircuit) -> Result<QrackCircuit, QrackError> {
+        // This circuit and "other" side by side, with other's qubits numbered
+        // after this circuit's
+        //
+        // Raises:
+        //     Io: "other" couldn't be read back from Qrack.
+        let out = self.clone();
+        let offset = self.get_qubit_count();
+        let map: Vec<u64> = (offset..(offset + other.get_qubit_count())).collect();
+        out.append(other, &map)?;
+        Ok(out)
+    }
+
+    pub fn remap(&self, map: &HashMap<u64, u64>) -> Result<QrackCircuit, QrackError> {
+        // Copy of the circuit with every qubit "q" moved to `map[q]`, (qubits
+        // absent from "map" stay put)
+        //
+        // Raises:
+        //     InvalidArgument: two qubits would move to the same one.
+        //     Io: the circuit couldn't be read back from Qrack.
+        let to = |q: u64| *map.get(&q).unwrap_or(&q);
+        let images: BTreeSet<u64> = (0..self.get_qubit_count()).map(to).collect();
+        if images.len() as u64 != self.get_qubit_count() {
+            return Err(QrackError::InvalidArgument("qubit map sends two qubits to one".to_string()));
+        }
+        let out = QrackCircuit::new();
+        for i in self.instructions()? {
+            out.append_instruction(&i.remapped(to)?);
+        }
+        Ok(out)
+    }
+
+    pub fn controlled(&self, controls: &[u64], anti_controls: &[u64]) -> Result<QrackCircuit, QrackError> {
+        // Copy of the circuit that only acts if every qubit of "controls" is
+        // `|1>` and every qubit of "anti_controls" is `|0>`
+        //
+        // Every payload gains the extra controls, as in "qcircuit_append_mc".
+        //
+        // Raises:
+        //     InvalidArgument: a control repeats or is used by the circuit, or
+        //         a gate would have 64 controls or more.
+        //     Io: the circuit couldn't be read back from Qrack.
+        let instructions = self.instructions()?;
+        let used: BTreeSet<u64> = instructions.iter().flat_map(|i| i.qubits()).collect();
+        if let Some(q) = controls.iter().chain(anti_controls).find(|q| used.contains(q)) {
+            return Err(QrackError::InvalidArgument(format!("control {} is used by the circuit", q)));
+        }
+        let out = QrackCircuit::new();
+        for i in &instructions {
+            out.append_instruction(&i.with_controls(controls, anti_controls)?);
+        }
+        Ok(out)
+    }
+
+    pub fn to_qasm3(&self) -> Result<String, QrackError> {
+        // Export the (optimized) circuit as OpenQASM 3.0
+        //
+        // Every gate payload becomes "U(theta, phi, lambda)" plus a "gphase()"
+        // for its global phase, under "ctrl @" and "negctrl @" modifiers that
+        // select its control permutation.
+        //
+        // Raises:
+        //     Io: the circuit couldn't be read back from Qrack.
+        //     InvalidArgument: Qrack's output is malformed.
+        let (qubits, gates) = parse_circuit(&self.dump()?)?;
+        Ok(qasm::to_qasm3(qubits, &gates))
+    }
+
+    pub fn stats(&self) -> Result<CircuitStats, QrackError> {
+        // Resource metrics of the (optimized) circuit
+        //
+        // Raises:
+        //     Io: the circuit couldn't be read back from Qrack.
+        let (qubits, instructions) = parse_circuit(&self.dump()?)?;
+        Ok(CircuitStats::from_instructions(qubits, &instructions))
+    }
+
+    pub fn past_light_cone_stats(&self, q: Vec<u64>) -> Result<CircuitStats, QrackError> {
+        // Resource metrics of `past_light_cone(q)`, to compare with `stats()`
+        self.past_light_cone(q).stats()
+    }
+
+    pub fn draw_ascii(&self, max_width: Option<usize>) -> Result<String, QrackError> {
+        // Text diagram of the (optimized) circuit
+        //
+        // Controls required to be `|1>` are drawn "@", and `|0>` "o". Swaps are
+        // drawn "x", and other gates as their names, from `gate_matrix::label()`.
+        //
+        // Args:
+        //     max_width(Option<usize>): wrap lines longer than this many characters
+        //
+        // Raises:
+        //     Io: the circuit couldn't be read back from Qrack.
+        let (qubits, instructions) = parse_circuit(&self.dump()?)?;
+        Ok(circuit_drawing::ascii(qubits, &instructions, max_width))
+    }
+
+    pub fn draw_svg(&self, max_width: Option<usize>) -> Result<String, QrackError> {
+        // SVG diagram of the (optimized) circuit, drawn as `draw_ascii()`
+        //
+        // Args:
+        //     max_width(Option<usize>): wrap diagrams wider than this many pixels
+        //
+        // Raises:
+        //     Io: the circuit couldn't be read back from Qrack.
+        let (qubits, instructions) = parse_circuit(&self.dump()?)?;
+        Ok(circuit_drawing::svg(qubits, &instructions, max_width))
+    }
+
+    pub fn to_unitary(&self) -> Result<Matrix, QrackError> {
+        // Unitary matrix of the circuit
+        //
+        // Column "j" is the state vector after running the circuit on basis
+        // state `|j>`, so this takes `2^n` runs on QrackSimulator.
+        //
+        // Raises:
+        //     Unsupported: the circuit is wider than MAX_UNITARY_QUBITS.
+        //     Native: Qrack reported an error code.
+        let width = self.get_qubit_count();
+        if width > MAX_UNITARY_QUBITS {
+            return Err(QrackError::Unsupported("Unitary of a circuit wider than MAX_UNITARY_QUBITS"));
+        }
+        self.unitary(width)
+    }
+
+    pub fn equivalent(&self, other: &QrackCircuit, up_to_global_phase: bool, tol: f64) -> Result<bool, QrackError> {
+        // Whether two circuits apply the same operator
+        //
+        // Up to MAX_UNITARY_QUBITS, the unitaries are compared element by
+        // element. Wider circuits are compared with Hadamard tests of
+        // `<psi|other^-1 self|psi>`, on random entangled input states "psi"
+        // and one extra qubit, so no state vector is copied out of Qrack.
+        //
+        // Args:
+        //     other(&QrackCircuit): circuit to compare
+        //     up_to_global_phase(bool): ignore a global phase difference
+        //     tol(f64): largest allowed element difference, or, for wider
+        //         circuits, largest allowed `1 - Re<psi|other^-1 self|psi>`,
+        //         (or `1 - |...|` up to global phase)
+        //
+        // Raises:
+        //     Native: Qrack reported an error code.
+        //     Io: a circuit couldn't be read back from Qrack.
+        //     InvalidArgument: a gate has 63 controls, so can't take one more.
+        let width = self.get_qubit_count().max(other.get_qubit_count());
+        if width <= MAX_UNITARY_QUBITS {
+            return Ok(self.unitary(width)?.approx_eq(&other.unitary(width)?, up_to_global_phase, tol));
+        }
+        let qubits: Vec<u64> = (0..width).collect();
+        let (a, b) = (self.controlled(&[width], &[])?, other.inverse().controlled(&[width], &[])?);
+        let mut states = RandomStates::new(0);
+        for _ in 0..EQUIVALENCE_TRIALS {
+            let re = QrackSimulator::new(width + 1)?;
+            states.prepare(&re, &qubits)?;
+            re.h(width)?;
+            let im = re.clone();
+            im.adjs(width)?;
+            let mut overlap = [0.0; 2];
+            for (qsim, o) in [re, im].iter().zip(overlap.iter_mut()) {
+                a.run(qsim)?;
+                b.run(qsim)?;
+                qsim.h(width)?;
+                // `P(|0>) = (1 + Re<psi|U|psi>)/2`, (or "Im", after "adjs")
+                *o = 1.0 - 2.0 * qsim.prob(width)?;
+            }
+            let agreement = if up_to_global_phase { overlap[0].hypot(overlap[1]) } else { overlap[0] };
+            if 1.0 - agreement > tol {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn unitary(&self, width: u64) -> Result<Matrix, QrackError> {
+        // Unitary of the circuit, acting on "width" qubits
+        if width == 0 {
+            return Ok(Matrix::identity(1));
+        }
+        let qsim = QrackSimulator::new(width)?;
+        let columns = (0..(1usize << width))
+            .map(|j| {
+                qsim.reset_all()?;
+                for q in (0..width).filter(|q| (j >> q) & 1 == 1) {
+                    qsim.x(q)?;
+                }
+                self.run(&qsim)?;
+                qsim.out_ket::<f64>()
+            })
+            .collect::<Result<Vec<Vec<Complex<f64>>>, QrackError>>()?;
+        Ok(Matrix::from_columns(columns))
+    }
+
+    fn dump(&self) -> Result<String, QrackError> {
+        // Qrack's text serialization of the circuit
+        //
+        // The C API only exposes `qcircuit_out_to_file()`, so this writes a
+        // file in a private temporary directory and reads it back.
+        let dir = PrivateTempDir::new()?;
+        let path = dir.path().join("circuit.qc");
+        self.out_to_file(&path)?;
+        Ok(fs::read_to_string(&path)?)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for QrackCircuit {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // As the string of `to_bytes()`, (by way of a private temporary file)
+        serializer.serialize_str(&self.dump().map_err(serde::ser::Error::custom)?)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for QrackCircuit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = <String as serde::Deserialize>::deserialize(deserializer)?;
+        QrackCircuit::from_bytes(text.as_bytes()).map_err(serde::de::Error::custom)
+    }
+}
+
+struct PrivateTempDir(PathBuf);
+
+impl PrivateTempDir {
+    fn new() -> io::Result<Self> {
+        // Fresh directory under the system temporary directory, that only
+        // this user can access
+        //
+        // The name is random, and creation fails rather than reuse anything
+        // already there, so another user can't plant a file or a symlink in it.
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        loop {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u32(std::process::id());
+            hasher.write_usize(COUNT.fetch_add(1, Ordering::Relaxed));
+            let path = env::temp_dir().join(format!("qook-{:016x}", hasher.finish()));
+            match builder.create(&path) {
+                Ok(()) => return Ok(PrivateTempDir(path)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e)
+            }
         }
     }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for PrivateTempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+pub(crate) fn all_ones(n: usize) -> u64 {
+    // Control permutation with the low "n" bits set
+    if n >= 64 { u64::MAX } else { (1 << n) - 1 }
 }
@@ -5,11 +5,71 @@
 // Use of this source code is governed by an MIT-style license that can be
 // found in the LICENSE file or at https://opensource.org/licenses/MIT.
 
+use qasm::QasmError;
+use simulator_builder::ConfigError;
+
 #[derive(Debug)]
-pub struct QrackError;
+pub enum QrackError {
+    // The C++ library raised an exception, reported through `get_error()`.
+    Native(i32),
+    // Vector arguments have unexpected lengths.
+    LengthMismatch { expected: usize, actual: usize },
+    // Qubit index is not less than the qubit count.
+    QubitOutOfRange { qubit: u64, count: u64 },
+    // Argument value is not valid for the operation.
+    InvalidArgument(String),
+    // The linked Qrack build does not provide the requested feature.
+    Unsupported(&'static str),
+    // Reading or writing a file failed.
+    Io(std::io::Error),
+    // The simulator's layer stack doesn't support the operation.
+    InvalidConfig(ConfigError),
+    // OpenQASM source failed to parse.
+    Qasm(QasmError)
+}
 
 impl std::fmt::Display for QrackError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "QrackSimulator C++ library raised exception, (or Vector arguments have unexpected lengths)")
+        match self {
+            QrackError::Native(code) =>
+                write!(f, "QrackSimulator C++ library raised exception (error code {})", code),
+            QrackError::LengthMismatch{ expected, actual } =>
+                write!(f, "Vector argument has length {}, expected {}", actual, expected),
+            QrackError::QubitOutOfRange{ qubit, count } =>
+                write!(f, "Qubit index {} is out of range for {} qubit(s)", qubit, count),
+            QrackError::InvalidArgument(reason) =>
+                write!(f, "Invalid argument: {}", reason),
+            QrackError::Unsupported(feature) =>
+                write!(f, "{} is not available in the linked Qrack build", feature),
+            QrackError::Io(_) =>
+                write!(f, "File I/O failed"),
+            QrackError::InvalidConfig(_) =>
+                write!(f, "Invalid QrackSimulator configuration"),
+            QrackError::Qasm(e) =>
+                write!(f, "OpenQASM error at {}", e)
+        }
+    }
+}
+
+impl std::error::Error for QrackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            QrackError::Io(e) => Some(e),
+            QrackError::InvalidConfig(e) => Some(e),
+            QrackError::Qasm(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for QrackError {
+    fn from(e: std::io::Error) -> Self {
+        QrackError::Io(e)
+    }
+}
+
+impl From<ConfigError> for QrackError {
+    fn from(e: ConfigError) -> Self {
+        QrackError::InvalidConfig(e)
     }
 }
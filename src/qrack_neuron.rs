@@ -78,10 +78,11 @@ impl QrackNeuron<'_> {
         }
     }
     fn check_error(&self) -> Result<(), QrackError> {
-        if self.get_error() != 0 {
-            return Err(QrackError{});
+        let code = self.get_error();
+        if code != 0 {
+            return Err(QrackError::Native(code));
         }
-        return Ok(());
+        Ok(())
     }
     
     // constructors
@@ -105,8 +106,9 @@ impl QrackNeuron<'_> {
                 a,
                 t
             );
-            if qrack_system::get_error(nid) != 0 {
-                return Err(QrackError{});
+            let code = qrack_system::get_error(nid);
+            if code != 0 {
+                return Err(QrackError::Native(code));
             }
         }
         let amp_cnt = 1 << (ctrls.len() + 1);
@@ -158,9 +160,7 @@ impl QrackNeuron<'_> {
         unsafe {
             qrack_system::get_qneuron_angles(self.nid, result.as_mut_ptr())
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -220,9 +220,7 @@ impl QrackNeuron<'_> {
         unsafe {
             result = qrack_system::qneuron_predict(self.nid, e, r);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -242,9 +240,7 @@ impl QrackNeuron<'_> {
         unsafe {
             result = qrack_system::qneuron_unpredict(self.nid, e);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -0,0 +1,32 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+pub trait QrackReal: Copy + Default {
+    // Floating point type that can cross the Qrack FFI boundary
+    //
+    // Qrack is built with either single ("FPPOW" 5) or double ("FPPOW" 6)
+    // precision, and the generated bindings use the matching Rust type.
+    // Conversions go through f64.
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl QrackReal for f32 {
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl QrackReal for f64 {
+    fn from_f64(v: f64) -> Self {
+        v
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
@@ -3,16 +3,34 @@
 // Use of this source code is governed by an MIT-style license that can be
 // found in the LICENSE file or at https://opensource.org/licenses/MIT.
 
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io;
+use std::os::raw::{c_char, c_void};
+use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use num_complex::Complex;
+use hamiltonian::Hamiltonian;
 use pauli::Pauli;
+use pauli_string::PauliSum;
 use qrack_error::QrackError;
+use qrack_real::QrackReal;
 use qrack_system;
+use simulator_builder::{ConfigError, SimulatorBuilder, SimulatorConfig};
 
 pub struct QrackSimulator {
     // Interface for all the QRack functionality.
     //
     // Attributes:
     //     sid(i64): Corresponding simulator id.
-    sid: u64
+    //     config(SimulatorConfig): Layer stack the simulator was built with.
+    sid: u64,
+    config: SimulatorConfig
 }
 
 impl Clone for QrackSimulator {
@@ -21,7 +39,7 @@ impl Clone for QrackSimulator {
         unsafe {
             sid = qrack_system::init_clone(self.sid);
         }
-        Self{ sid }
+        Self{ sid, config: self.config }
     }
 }
 
@@ -39,12 +57,26 @@ impl QrackSimulator {
         let sid;
         unsafe {
             sid = qrack_system::init_count(qubit_count, false);
-            if qrack_system::get_error(sid) != 0 {
-                return Err(QrackError{});
+            let code = qrack_system::get_error(sid);
+            if code != 0 {
+                return Err(QrackError::Native(code));
             }
         }
-        return Ok(Self{ sid });
+        // "init_count()" is the default stack with multi-device Schmidt decomposition.
+        let config = SimulatorConfig{ schmidt_decompose_multi: true, ..SimulatorConfig::default() };
+        return Ok(Self{ sid, config });
     }
+
+    pub fn builder() -> SimulatorBuilder {
+        // Start building a simulator with an explicit layer stack
+        //
+        // Example:
+        //     QrackSimulator::builder().qubits(20).tensor_network(true).stabilizer_hybrid(true).build()
+        SimulatorBuilder::new()
+    }
+
+    #[deprecated(note = "use QrackSimulator::builder()")]
+    #[allow(clippy::too_many_arguments)]
     pub fn new_layers(qubit_count: u64,
         is_tensor_network: bool,
         is_schmidt_decompose_multi: bool,
@@ -56,45 +88,45 @@ impl QrackSimulator {
         is_opencl: bool,
         is_host_pointer: bool) -> Result<Self, QrackError> {
 
+        SimulatorBuilder::new()
+            .qubits(qubit_count)
+            .tensor_network(is_tensor_network)
+            .schmidt_decompose_multi(is_schmidt_decompose_multi)
+            .schmidt_decompose(is_schmidt_decompose)
+            .stabilizer_hybrid(is_stabilizer_hybrid)
+            .binary_decision_tree(is_binary_decision_tree)
+            .paged(is_paged)
+            .cpu_gpu_hybrid(is_cpu_gpu_hybrid)
+            .opencl(is_opencl)
+            .host_pointer(is_host_pointer)
+            .build()
+    }
+
+    pub(crate) fn from_config(qubit_count: u64, config: SimulatorConfig) -> Result<Self, QrackError> {
         let sid;
-        if is_tensor_network
-            && is_schmidt_decompose
-            && is_stabilizer_hybrid
-            && !is_binary_decision_tree
-            && is_paged
-            && is_cpu_gpu_hybrid
-            && is_opencl {
-            if is_schmidt_decompose_multi {
-                unsafe {
-                    sid = qrack_system::init_count(qubit_count, is_host_pointer);
-                }
-            } else {
-                unsafe {
-                    sid = qrack_system::init_count_pager(qubit_count, is_host_pointer);
-                }
-            }
-        } else {
-            unsafe {
-                sid = qrack_system::init_count_type(qubit_count,
-                                                              is_tensor_network,
-                                                              is_schmidt_decompose_multi,
-                                                              is_schmidt_decompose,
-                                                              is_stabilizer_hybrid,
-                                                              is_binary_decision_tree,
-                                                              is_paged,
-                                                              false,
-                                                              is_cpu_gpu_hybrid,
-                                                              is_opencl,
-                                                              is_host_pointer);
-            }
-        }
-
         unsafe {
-            if qrack_system::get_error(sid) != 0 {
-                return Err(QrackError{});
+            sid = qrack_system::init_count_type(qubit_count,
+                                                config.tensor_network,
+                                                config.schmidt_decompose_multi,
+                                                config.schmidt_decompose,
+                                                config.stabilizer_hybrid,
+                                                config.binary_decision_tree,
+                                                config.paged,
+                                                config.zx_fusion,
+                                                config.cpu_gpu_hybrid,
+                                                config.opencl,
+                                                config.host_pointer);
+            let code = qrack_system::get_error(sid);
+            if code != 0 {
+                return Err(QrackError::Native(code));
             }
         }
-        return Ok(Self{ sid });
+        Ok(Self{ sid, config })
+    }
+
+    pub fn config(&self) -> &SimulatorConfig {
+        // Layer stack this simulator was constructed with
+        &self.config
     }
 
     // non-quantum
@@ -105,10 +137,11 @@ impl QrackSimulator {
     }
 
     pub fn check_error(&self) -> Result<(), QrackError> {
-        if self.get_error() != 0 {
-            return Err(QrackError{});
+        let code = self.get_error();
+        if code != 0 {
+            return Err(QrackError::Native(code));
         }
-        return Ok(())
+        Ok(())
     }
 
     pub fn get_sid(&self) -> u64 {
@@ -129,6 +162,116 @@ impl QrackSimulator {
         self.check_error()
     }
 
+    pub fn random_choice(&self, p: &[f64]) -> Result<usize, QrackError> {
+        // Weighted random choice
+        //
+        // Draws an index with probability proportional to its weight, using
+        // the simulator's random number generator, so results are
+        // reproducible under `seed()`.
+        //
+        // Args:
+        //     p(&[f64]): non-negative weights, (need not be normalized)
+        //
+        // Raises:
+        //     InvalidArgument: weights are empty, negative, non-finite or all 0.
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     Index of the chosen weight
+
+        validate_weights(p)?;
+        let mut _p = p.to_vec();
+        self.draw_choice(&mut _p)
+    }
+
+    pub fn random_choices(&self, p: &[f64]) -> Result<RandomChoices<'_>, QrackError> {
+        // Repeated weighted random choice
+        //
+        // Validates the weights once and returns an (endless) iterator of
+        // draws, as by `random_choice()`.
+        //
+        // Args:
+        //     p(&[f64]): non-negative weights, (need not be normalized)
+        //
+        // Raises:
+        //     InvalidArgument: weights are empty, negative, non-finite or all 0.
+
+        validate_weights(p)?;
+        Ok(RandomChoices{ simulator: self, p: p.to_vec() })
+    }
+
+    fn draw_choice(&self, p: &mut [f64]) -> Result<usize, QrackError> {
+        let result;
+        unsafe {
+            result = qrack_system::random_choice(self.sid, p.len() as qrack_system::size_t, p.as_mut_ptr());
+        }
+        self.check_error()?;
+        Ok(result as usize)
+    }
+
+    pub fn save_stabilizer<P: AsRef<Path>>(&self, path: P) -> Result<(), QrackError> {
+        // Output stabilizer state to file
+        //
+        // Writes the stabilizer tableau to a file named by "path". The
+        // simulator must be built with `SimulatorConfig::stabilizer()` layers.
+        // Only that layer configuration is checked; whether the current
+        // state is Clifford is not, so the caller must ensure it.
+        //
+        // Args:
+        //     path(AsRef<Path>): name of file
+        //
+        // Raises:
+        //     InvalidConfig: simulator layers can't represent a stabilizer state.
+        //     Io: file can't be written.
+        //     Native: Qrack reported an error code.
+
+        if !self.config.is_stabilizer_representable() {
+            return Err(QrackError::InvalidConfig(ConfigError::NotStabilizer));
+        }
+        let path = path.as_ref();
+        let filename = path_to_cstring(path)?;
+        // The C++ library doesn't report stream failures, so open here first.
+        File::create(path)?;
+        unsafe {
+            qrack_system::qstabilizer_out_to_file(self.sid, filename.as_ptr() as *mut c_char);
+        }
+        self.check_error()?;
+        if fs::metadata(path)?.len() == 0 {
+            return Err(QrackError::Io(io::Error::other("no stabilizer data was written")));
+        }
+        Ok(())
+    }
+
+    pub fn load_stabilizer<P: AsRef<Path>>(path: P) -> Result<QrackSimulator, QrackError> {
+        // Input stabilizer state from file
+        //
+        // Reads a stabilizer tableau written by `save_stabilizer()` into a new
+        // simulator with `SimulatorConfig::stabilizer()` layers.
+        //
+        // Args:
+        //     path(AsRef<Path>): name of file
+        //
+        // Raises:
+        //     Io: file can't be read, or doesn't start with a qubit count.
+        //     Native: Qrack reported an error code.
+        //
+        // Returns:
+        //     QrackSimulator in the saved state
+
+        let path = path.as_ref();
+        let filename = path_to_cstring(path)?;
+        let contents = fs::read_to_string(path)?;
+        let qubit_count = contents.lines().next()
+            .and_then(|l| l.trim().parse::<u64>().ok())
+            .ok_or_else(|| QrackError::Io(io::Error::new(io::ErrorKind::InvalidData, "stabilizer file does not start with a qubit count")))?;
+        let out = QrackSimulator::from_config(qubit_count, SimulatorConfig::stabilizer())?;
+        unsafe {
+            qrack_system::qstabilizer_in_from_file(out.sid, filename.as_ptr() as *mut c_char);
+        }
+        out.check_error()?;
+        Ok(out)
+    }
+
     // standard gates
 
     // single-qubits gates
@@ -348,7 +491,7 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if b.len() != q.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: b.len() });
         }
         let mut _b = b.to_vec();
         let mut _q = q.to_vec();
@@ -904,7 +1047,7 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if b.len() != q.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: b.len() });
         }
         let mut _b = b.to_vec();
         let mut _cs = cs.to_vec();
@@ -915,6 +1058,33 @@ impl QrackSimulator {
         self.check_error()
     }
 
+    pub fn time_evolve(&self, h: &Hamiltonian, t: f64) -> Result<(), QrackError> {
+        // Hamiltonian time evolution
+        //
+        // Applies `e^{-i*H*t}` for the Hamiltonian "h", without manual
+        // Trotterization.
+        //
+        // Args:
+        //     h(&Hamiltonian): Hamiltonian terms
+        //     t(f64): evolution time
+        //
+        // Raises:
+        //     QubitOutOfRange: a term acts on a qubit outside the simulator.
+        //     RuntimeError: QrackSimulator raised an exception.
+
+        let count = self.num_qubits()?;
+        if h.qubit_count() > count {
+            return Err(QrackError::QubitOutOfRange{ qubit: h.qubit_count() - 1, count });
+        }
+        let (mut teos, mut mtrx) = h.marshal();
+        unsafe {
+            qrack_system::TimeEvolve(self.sid, t, teos.len() as u64,
+                teos.as_mut_ptr() as *mut qrack_system::_QrackTimeEvolveOpHeader,
+                mtrx.len() as u64, mtrx.as_mut_ptr());
+        }
+        self.check_error()
+    }
+
     pub fn swap(&self, qi1: u64, qi2: u64) -> Result<(), QrackError> {
         // Swap Gate
         //
@@ -1050,9 +1220,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::M(self.sid, q);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -1075,9 +1243,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::ForceM(self.sid, q, r);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -1097,9 +1263,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::MAll(self.sid);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -1120,7 +1284,7 @@ impl QrackSimulator {
         //     Measurement result.
 
         if b.len() != q.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: b.len() });
         }
         let mut _b = b.to_vec();
         let mut _q = q.to_vec();
@@ -1128,9 +1292,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::Measure(self.sid, _b.len() as u64, _b.as_mut_ptr() as *mut i32, _q.as_mut_ptr());
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -1155,9 +1317,7 @@ impl QrackSimulator {
         unsafe {
             qrack_system::MeasureShots(self.sid, _q.len() as u64, _q.as_mut_ptr(), s, result.as_mut_ptr());
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -1276,7 +1436,7 @@ impl QrackSimulator {
         //    RuntimeError: QrackSimulator raised an exception.
 
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _q = q.to_vec();
@@ -1304,7 +1464,7 @@ impl QrackSimulator {
         //    RuntimeError: QrackSimulator raised an exception.
 
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _q = q.to_vec();
@@ -1331,10 +1491,10 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if a.len() != m.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: a.len(), actual: m.len() });
         }
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _m = m.to_vec();
@@ -1363,10 +1523,10 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if a.len() != m.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: a.len(), actual: m.len() });
         }
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _m = m.to_vec();
@@ -1394,10 +1554,10 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if a.len() != m.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: a.len(), actual: m.len() });
         }
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _m = m.to_vec();
@@ -1473,7 +1633,7 @@ impl QrackSimulator {
         //    RuntimeError: QrackSimulator raised an exception.
 
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _c = c.to_vec();
@@ -1503,7 +1663,7 @@ impl QrackSimulator {
         //    RuntimeError: QrackSimulator raised an exception.
 
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _c = c.to_vec();
@@ -1533,10 +1693,10 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if a.len() != m.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: a.len(), actual: m.len() });
         }
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _c = c.to_vec();
@@ -1568,10 +1728,10 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if a.len() != m.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: a.len(), actual: m.len() });
         }
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _c = c.to_vec();
@@ -1602,10 +1762,10 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if a.len() != m.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: a.len(), actual: m.len() });
         }
         if q.len() != o.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: o.len() });
         }
         let mut _a = a.to_vec();
         let mut _c = c.to_vec();
@@ -1634,7 +1794,7 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if (8 * t.len()) < ((1 << qi.len()) * qv.len()) {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: ((1 << qi.len()) * qv.len()).div_ceil(8), actual: t.len() });
         }
         let mut _qi = qi.to_vec();
         let mut _qv = qv.to_vec();
@@ -1660,7 +1820,7 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if (8 * t.len()) < ((1 << qi.len()) * qv.len()) {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: ((1 << qi.len()) * qv.len()).div_ceil(8), actual: t.len() });
         }
         let mut _qi = qi.to_vec();
         let mut _qv = qv.to_vec();
@@ -1686,7 +1846,7 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if (8 * t.len()) < ((1 << qi.len()) * qv.len()) {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: ((1 << qi.len()) * qv.len()).div_ceil(8), actual: t.len() });
         }
         let mut _qi = qi.to_vec();
         let mut _qv = qv.to_vec();
@@ -1712,7 +1872,7 @@ impl QrackSimulator {
         //     RuntimeError: QrackSimulator raised an exception.
 
         if (8 * t.len()) < (1 << q.len()) {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: (1usize << q.len()).div_ceil(8), actual: t.len() });
         }
         let mut _q = q.to_vec();
         let mut _t = t.to_vec();
@@ -2035,9 +2195,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::release(self.sid, q);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2057,9 +2215,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::num_qubits(self.sid);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2107,9 +2263,8 @@ impl QrackSimulator {
         unsafe {
             other.sid = qrack_system::Decompose(self.sid, _q.len() as u64, _q.as_mut_ptr());
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        other.config = self.config;
+        self.check_error()?;
         Ok(other)
     }
 
@@ -2134,7 +2289,16 @@ impl QrackSimulator {
         self.check_error()
     }
 
-    // pub fn in_ket(&self, ket: *mut f32) -> Result<(), QrackError> {
+    fn ket_len(&self) -> Result<usize, QrackError> {
+        // Amplitude count of the full state vector, `2^num_qubits()`
+        let n = self.num_qubits()?;
+        if n >= usize::BITS as u64 {
+            return Err(QrackError::Unsupported("State vector of this width"));
+        }
+        Ok(1usize << n)
+    }
+
+    pub fn in_ket<T: QrackReal>(&self, ket: &[Complex<T>]) -> Result<(), QrackError> {
         // Set state vector
         //
         // Set state vector for the selected simulator ID.
@@ -2142,23 +2306,28 @@ impl QrackSimulator {
         // Warning: State vector is not always the internal representation, leading
         // to sub-optimal performance of the method.
         //
-        // Note: By design of this interface layer between Rust and C++, Qrack
-        // cannot guarantee that your input ket is not insecurely mutated, but it
-        // "promises" you that it does not intend to ever mutate it.
+        // Amplitudes are converted to the floating point precision of the
+        // linked Qrack build.
         //
         // Args:
-        //     ket(*mut f32): the state vector to which simulator will be set
+        //     ket(&[Complex<T>]): the state vector to which simulator will be set,
+        //         of length `2^num_qubits()`
         //
         // Raises:
+        //     LengthMismatch: ket length is not `2^num_qubits()`.
         //     RuntimeError: Not implemented for the given builds.
 
-    //     unsafe {
-    //         qrack_system::InKet(self.sid, ket);
-    //     }
-    //     self.check_error()
-    // }
+        let expected = self.ket_len()?;
+        if ket.len() != expected {
+            return Err(QrackError::LengthMismatch{ expected, actual: ket.len() });
+        }
+        unsafe {
+            write_ket(qrack_system::InKet, self.sid, ket);
+        }
+        self.check_error()
+    }
 
-    // pub fn out_ket(self, ket: *mut f32) -> Result<(), QrackError> {
+    pub fn out_ket<T: QrackReal>(&self) -> Result<Vec<Complex<T>>, QrackError> {
         // Get state vector
         //
         // Returns the raw state vector of the simulator.
@@ -2166,17 +2335,70 @@ impl QrackSimulator {
         // Warning: State vector is not always the internal representation, leading
         // to sub-optimal performance of the method.
         //
+        // Raises:
+        //     RuntimeError: Not implemented for the given builds.
+        //
+        // Returns:
+        //     Vec<Complex<T>> of length `2^num_qubits()`
+
+        let len = self.ket_len()?;
+        let result;
+        unsafe {
+            result = read_ket(qrack_system::OutKet, self.sid, len);
+        }
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn for_each_amplitude<F>(&self, mut f: F) -> Result<(), QrackError>
+        where F: FnMut(usize, Complex<f64>) -> ControlFlow<()> {
+        // Iterate over amplitudes
+        //
+        // Streams the amplitudes of the state vector, by permutation index,
+        // to the closure "f", without materializing the full ket. Returning
+        // `ControlFlow::Break(())` from "f" stops the iteration early.
+        //
+        // If "f" panics, the iteration stops and the panic resumes after
+        // control returns from the C++ library.
+        //
         // Args:
-        //     ket(*mut f32): the state vector to which simulator will be output
+        //     f(FnMut(usize, Complex<f64>) -> ControlFlow<()>): amplitude visitor
         //
         // Raises:
-        //     RuntimeError: Not implemented for the given builds.
+        //     RuntimeError: QrackSimulator raised an exception.
 
-    //     unsafe {
-    //         qrack_system::OutKet(self.sid, ket);
-    //     }
-    //     self.check_error()
-    // }
+        let mut f_ref: &mut dyn FnMut(usize, Complex<f64>) -> ControlFlow<()> = &mut f;
+        let ctx = &mut f_ref as *mut _ as *mut c_void;
+        with_callback_context(ctx, || unsafe {
+            qrack_system::Dump(self.sid, Some(amplitude_trampoline));
+        });
+        self.check_error()
+    }
+
+    pub fn qubit_ids(&self) -> Result<Vec<u64>, QrackError> {
+        // Get live qubit ids
+        //
+        // Lists the ids of qubits currently allocated in the simulator,
+        // reflecting any `allocate_qubit()` and `release()` calls.
+        //
+        // Raises:
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     Vec<u64> of qubit ids
+
+        let mut result = Vec::new();
+        {
+            let mut push = |id: u64| result.push(id);
+            let mut f_ref: &mut dyn FnMut(u64) = &mut push;
+            let ctx = &mut f_ref as *mut _ as *mut c_void;
+            with_callback_context(ctx, || unsafe {
+                qrack_system::DumpIds(self.sid, Some(id_trampoline));
+            });
+        }
+        self.check_error()?;
+        Ok(result)
+    }
 
     pub fn prob_perm(&self, q: Vec<u64>, c: Vec<bool>) -> Result<f64, QrackError> {
         // Probability of permutation
@@ -2196,7 +2418,7 @@ impl QrackSimulator {
         //     value in "c[i]", at once
 
         if q.len() != c.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: c.len() });
         }
         let mut _q = q.to_vec();
         let mut _c = c.to_vec();
@@ -2204,9 +2426,39 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::PermutationProb(self.sid, _q.len() as u64, _q.as_mut_ptr(), _c.as_mut_ptr());
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn prob_perm_rdm(&self, q: Vec<u64>, c: Vec<bool>, r: bool) -> Result<f64, QrackError> {
+        // Probability of permutation, (tracing out the reduced density matrix
+        // without stabilizer ancillary qubits)
+        //
+        // Get the probability that the qubit IDs in "q" have the truth values
+        // in "c", directly corresponding by vector index.
+        //
+        // Args:
+        //    q(Vec<u64>): qubit ids
+        //    c(Vec<bool>): qubit truth values
+        //    r(bool): "Rounding" on/off
+        //
+        // Raises:
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     probability that each qubit in "q[i]" has corresponding truth
+        //     value in "c[i]", at once
+
+        if q.len() != c.len() {
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: c.len() });
+        }
+        let mut _q = q.to_vec();
+        let mut _c = c.to_vec();
+        let result:f64;
+        unsafe {
+            result = qrack_system::PermutationProbRdm(self.sid, _q.len() as u64, _q.as_mut_ptr(), _c.as_mut_ptr(), r);
         }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2228,9 +2480,31 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::Prob(self.sid, q);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn prob_rdm(&self, q: u64) -> Result<f64, QrackError> {
+        // Probability of `|1>`, (tracing out the reduced density matrix
+        // without stabilizer ancillary qubits)
+        //
+        // Get the probability that a qubit is in the `|1>` state, without
+        // flushing buffered non-Clifford gates of a near-Clifford simulator.
+        //
+        // Args:
+        //     q(u64): qubit id
+        //
+        // Raises:
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     probability of qubit being in `|1>`
+
+        let result:f64;
+        unsafe {
+            result = qrack_system::ProbRdm(self.sid, q);
         }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2254,9 +2528,157 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::PermutationExpectation(self.sid, _c.len() as u64, _c.as_mut_ptr());
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn permutation_expectation_rdm(&self, c: Vec<u64>, r: bool) -> Result<f64, QrackError> {
+        // Permutation expectation value, (tracing out the reduced density
+        // matrix without stabilizer ancillary qubits)
+        //
+        // Get the permutation expectation value, based upon the order of
+        // input qubits.
+        //
+        // Args:
+        //     c(Vec<u64>): permutation (as u64 words, low-to-high)
+        //     r(bool): "Rounding" on/off
+        //
+        // Raises:
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     Expectation value
+
+        let mut _c = c.to_vec();
+        let result:f64;
+        unsafe {
+            result = qrack_system::PermutationExpectationRdm(self.sid, _c.len() as u64, _c.as_mut_ptr(), r);
+        }
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn factorized_expectation(&self, q: &[u64], c: &[u64]) -> Result<f64, QrackError> {
+        // Factorized expectation value
+        //
+        // Get the expectation value of a classical value assigned to each
+        // qubit, for `|0>` and `|1>` respectively, summed over qubits.
+        //
+        // Args:
+        //     q(&[u64]): qubit ids
+        //     c(&[u64]): per-qubit values, `|0>` then `|1>`, of length `2 * q.len()`
+        //
+        // Raises:
+        //     LengthMismatch: "c" is not twice the length of "q".
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     Expectation value
+
+        if c.len() != 2 * q.len() {
+            return Err(QrackError::LengthMismatch{ expected: 2 * q.len(), actual: c.len() });
+        }
+        let mut _q = q.to_vec();
+        let mut _c = c.to_vec();
+        let result:f64;
+        unsafe {
+            // Each value is a single 64-bit word.
+            result = qrack_system::FactorizedExpectation(self.sid, _q.len() as u64, _q.as_mut_ptr(), 1, _c.as_mut_ptr());
         }
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn factorized_expectation_rdm(&self, q: &[u64], c: &[u64], r: bool) -> Result<f64, QrackError> {
+        // Factorized expectation value, (tracing out the reduced density
+        // matrix without stabilizer ancillary qubits)
+        //
+        // Get the expectation value of a classical value assigned to each
+        // qubit, for `|0>` and `|1>` respectively, summed over qubits.
+        //
+        // Args:
+        //     q(&[u64]): qubit ids
+        //     c(&[u64]): per-qubit values, `|0>` then `|1>`, of length `2 * q.len()`
+        //     r(bool): "Rounding" on/off
+        //
+        // Raises:
+        //     LengthMismatch: "c" is not twice the length of "q".
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     Expectation value
+
+        if c.len() != 2 * q.len() {
+            return Err(QrackError::LengthMismatch{ expected: 2 * q.len(), actual: c.len() });
+        }
+        let mut _q = q.to_vec();
+        let mut _c = c.to_vec();
+        let result:f64;
+        unsafe {
+            // Each value is a single 64-bit word.
+            result = qrack_system::FactorizedExpectationRdm(self.sid, _q.len() as u64, _q.as_mut_ptr(), 1, _c.as_mut_ptr(), r);
+        }
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn factorized_expectation_fp(&self, q: &[u64], c: &[f64]) -> Result<f64, QrackError> {
+        // Factorized expectation value, (floating point values)
+        //
+        // Get the expectation value of a real value assigned to each qubit,
+        // for `|0>` and `|1>` respectively, summed over qubits. Values are
+        // converted to the floating point precision of the linked Qrack build.
+        //
+        // Args:
+        //     q(&[u64]): qubit ids
+        //     c(&[f64]): per-qubit values, `|0>` then `|1>`, of length `2 * q.len()`
+        //
+        // Raises:
+        //     LengthMismatch: "c" is not twice the length of "q".
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     Expectation value
+
+        if c.len() != 2 * q.len() {
+            return Err(QrackError::LengthMismatch{ expected: 2 * q.len(), actual: c.len() });
+        }
+        let mut _q = q.to_vec();
+        let mut _c = real_vec(c);
+        let result:f64;
+        unsafe {
+            result = qrack_system::FactorizedExpectationFp(self.sid, _q.len() as u64, _q.as_mut_ptr(), _c.as_mut_ptr());
+        }
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn factorized_expectation_fp_rdm(&self, q: &[u64], c: &[f64], r: bool) -> Result<f64, QrackError> {
+        // Factorized expectation value, (floating point values, tracing out the
+        // reduced density matrix without stabilizer ancillary qubits)
+        //
+        // Args:
+        //     q(&[u64]): qubit ids
+        //     c(&[f64]): per-qubit values, `|0>` then `|1>`, of length `2 * q.len()`
+        //     r(bool): "Rounding" on/off
+        //
+        // Raises:
+        //     LengthMismatch: "c" is not twice the length of "q".
+        //     RuntimeError: QrackSimulator raised an exception.
+        //
+        // Returns:
+        //     Expectation value
+
+        if c.len() != 2 * q.len() {
+            return Err(QrackError::LengthMismatch{ expected: 2 * q.len(), actual: c.len() });
+        }
+        let mut _q = q.to_vec();
+        let mut _c = real_vec(c);
+        let result:f64;
+        unsafe {
+            result = qrack_system::FactorizedExpectationFpRdm(self.sid, _q.len() as u64, _q.as_mut_ptr(), _c.as_mut_ptr(), r);
+        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2277,7 +2699,7 @@ impl QrackSimulator {
         //     Expectation value
 
         if b.len() != q.len() {
-            return Err(QrackError{});
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: b.len() });
         }
         let mut _b = b.to_vec();
         let mut _q = q.to_vec();
@@ -2285,8 +2707,64 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::JointEnsembleProbability(self.sid, _b.len() as u64, _b.as_mut_ptr() as *mut i32, _q.as_mut_ptr());
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
+        self.check_error()?;
+        Ok(result)
+    }
+
+    pub fn expectation(&self, o: &PauliSum) -> Result<f64, QrackError> {
+        // Expectation value of an observable
+        //
+        // Terms with equal operators are merged, then split into qubit-wise
+        // commuting groups. Each group's qubits are rotated once into its
+        // shared basis, every term of the group is read as
+        // `1 - 2 * joint_ensemble_probability()` of its Z parity, and the
+        // rotation is undone.
+        //
+        // Args:
+        //     o(&PauliSum): observable
+        //
+        // Raises:
+        //     Native: Qrack reported an error code.
+        //
+        // Returns:
+        //     Expectation value
+
+        let o = o.simplify();
+        let mut result = 0.0;
+        for group in o.commuting_groups() {
+            let terms: Vec<_> = group.iter().map(|i| &o.terms()[*i]).collect();
+            let mut basis: Vec<(u64, Pauli)> = terms.iter().flat_map(|t| t.paulis().iter().cloned()).collect();
+            basis.sort_by_key(|(q, _)| *q);
+            basis.dedup();
+            for (q, b) in &basis {
+                match b {
+                    Pauli::PauliX => self.h(*q)?,
+                    Pauli::PauliY => {
+                        self.adjs(*q)?;
+                        self.h(*q)?;
+                    },
+                    _ => {}
+                }
+            }
+            for t in terms {
+                if t.is_identity() {
+                    result += t.coefficient();
+                    continue;
+                }
+                let q: Vec<u64> = t.paulis().iter().map(|(q, _)| *q).collect();
+                let z = vec![Pauli::PauliZ; q.len()];
+                result += t.coefficient() * (1.0 - 2.0 * self.joint_ensemble_probability(z, q)?);
+            }
+            for (q, b) in &basis {
+                match b {
+                    Pauli::PauliX => self.h(*q)?,
+                    Pauli::PauliY => {
+                        self.h(*q)?;
+                        self.s(*q)?;
+                    },
+                    _ => {}
+                }
+            }
         }
         Ok(result)
     }
@@ -2330,9 +2808,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::TrySeparate1Qb(self.sid, qi1);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2355,9 +2831,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::TrySeparate2Qb(self.sid, qi1, qi2);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2381,9 +2855,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::TrySeparateTol(self.sid, _qs.len() as u64, _qs.as_mut_ptr(), t);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2408,9 +2880,7 @@ impl QrackSimulator {
         unsafe {
             result = qrack_system::GetUnitaryFidelity(self.sid);
         }
-        if self.get_error() != 0 {
-            return Err(QrackError{});
-        }
+        self.check_error()?;
         Ok(result)
     }
 
@@ -2490,3 +2960,124 @@ impl QrackSimulator {
         self.check_error()
     }
 }
+
+pub(crate) fn path_to_cstring(path: &Path) -> Result<CString, QrackError> {
+    // File name in the form the C++ library takes
+    let name = path.to_str()
+        .ok_or_else(|| QrackError::InvalidArgument(format!("path {:?} is not valid UTF-8", path)))?;
+    CString::new(name)
+        .map_err(|_| QrackError::InvalidArgument(format!("path {:?} contains a NUL byte", path)))
+}
+
+pub struct RandomChoices<'a> {
+    // Iterator of weighted random choices from one distribution
+    //
+    // Attributes:
+    //     simulator(&QrackSimulator): simulator whose generator is drawn from
+    //     p(Vec<f64>): validated weights
+    simulator: &'a QrackSimulator,
+    p: Vec<f64>
+}
+
+impl Iterator for RandomChoices<'_> {
+    type Item = Result<usize, QrackError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.simulator.draw_choice(&mut self.p))
+    }
+}
+
+fn validate_weights(p: &[f64]) -> Result<(), QrackError> {
+    if p.is_empty() {
+        return Err(QrackError::InvalidArgument("weights are empty".to_string()));
+    }
+    if let Some(w) = p.iter().find(|w| !w.is_finite() || **w < 0.0) {
+        return Err(QrackError::InvalidArgument(format!("weight {} is negative or not finite", w)));
+    }
+    if p.iter().all(|w| *w == 0.0) {
+        return Err(QrackError::InvalidArgument("weights are all 0".to_string()));
+    }
+    Ok(())
+}
+
+fn real_vec<R: QrackReal>(v: &[f64]) -> Vec<R> {
+    // Buffer of library-precision floats, (the element type is inferred from
+    // the binding the buffer is passed to)
+    v.iter().map(|x| R::from_f64(*x)).collect()
+}
+
+// The element type "R" of these helpers is inferred from the generated
+// binding, so they follow the precision the linked Qrack was built with.
+unsafe fn write_ket<T: QrackReal, R: QrackReal>(f: unsafe extern "C" fn(qrack_system::uintq, *mut R), sid: u64, ket: &[Complex<T>]) {
+    let mut raw = Vec::with_capacity(2 * ket.len());
+    for amp in ket {
+        raw.push(R::from_f64(amp.re.to_f64()));
+        raw.push(R::from_f64(amp.im.to_f64()));
+    }
+    f(sid, raw.as_mut_ptr());
+}
+
+unsafe fn read_ket<T: QrackReal, R: QrackReal>(f: unsafe extern "C" fn(qrack_system::uintq, *mut R), sid: u64, len: usize) -> Vec<Complex<T>> {
+    let mut raw = vec![R::default(); 2 * len];
+    f(sid, raw.as_mut_ptr());
+    raw.chunks(2)
+        .map(|c| Complex::new(T::from_f64(c[0].to_f64()), T::from_f64(c[1].to_f64())))
+        .collect()
+}
+
+// The C callbacks of "Dump()" and "DumpIds()" carry no user data, so the
+// closure receiving them is parked in a thread-local for the duration of the
+// call. Qrack invokes these callbacks on the calling thread. A panic in the
+// closure is caught at the FFI boundary and resumed once the call returns.
+thread_local! {
+    static CALLBACK_CONTEXT: RefCell<(*mut c_void, Option<Box<dyn Any + Send>>)> = RefCell::new((ptr::null_mut(), None));
+}
+
+fn with_callback_context<R, F: FnOnce() -> R>(ctx: *mut c_void, body: F) -> R {
+    // Saving the previous context keeps nested iteration from the closure sound.
+    let saved = CALLBACK_CONTEXT.with(|c| c.replace((ctx, None)));
+    let result = body();
+    let (_, payload) = CALLBACK_CONTEXT.with(|c| c.replace(saved));
+    if let Some(payload) = payload {
+        panic::resume_unwind(payload);
+    }
+    result
+}
+
+fn current_callback() -> Option<*mut c_void> {
+    CALLBACK_CONTEXT.with(|c| {
+        let c = c.borrow();
+        if c.0.is_null() || c.1.is_some() { None } else { Some(c.0) }
+    })
+}
+
+fn store_panic(payload: Box<dyn Any + Send>) {
+    CALLBACK_CONTEXT.with(|c| c.borrow_mut().1 = Some(payload));
+}
+
+unsafe extern "C" fn amplitude_trampoline(i: qrack_system::size_t, re: f64, im: f64) -> bool {
+    let ctx = match current_callback() {
+        Some(ctx) => ctx,
+        None => return false
+    };
+    let f = &mut *(ctx as *mut &mut dyn FnMut(usize, Complex<f64>) -> ControlFlow<()>);
+    match panic::catch_unwind(AssertUnwindSafe(|| f(i as usize, Complex::new(re, im)))) {
+        Ok(ControlFlow::Continue(())) => true,
+        Ok(ControlFlow::Break(())) => false,
+        Err(payload) => {
+            store_panic(payload);
+            false
+        }
+    }
+}
+
+unsafe extern "C" fn id_trampoline(id: qrack_system::uintq) {
+    let ctx = match current_callback() {
+        Some(ctx) => ctx,
+        None => return
+    };
+    let f = &mut *(ctx as *mut &mut dyn FnMut(u64));
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| f(id))) {
+        store_panic(payload);
+    }
+}
@@ -0,0 +1,547 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use std::f64::consts::PI;
+
+use circuit_instruction::CircuitInstruction;
+use gate_matrix;
+use pauli::Pauli;
+use qrack_circuit::{all_ones, QrackCircuit};
+use qrack_error::QrackError;
+use qrack_simulator::QrackSimulator;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeasurementBoundary {
+    // Measurement that ended a recorded segment
+    //
+    // Attributes:
+    //     bases(Vec<Pauli>): measurement basis per qubit
+    //     qubits(Vec<u64>): measured qubits
+    //     result(Option<u64>): outcome, if the recording simulates, (bit "i"
+    //         is the result of "qubits[i]")
+    pub bases: Vec<Pauli>,
+    pub qubits: Vec<u64>,
+    pub result: Option<u64>
+}
+
+pub struct RecordingSimulator {
+    // Gate-level wrapper of QrackSimulator that mirrors every gate into a
+    // QrackCircuit
+    //
+    // Measurements aren't unitary, so each one closes the current circuit
+    // segment and starts the next. Segment "i" runs before "boundaries()[i]",
+    // which runs before segment "i + 1".
+    //
+    // Every single-qubit, controlled, swap-type, exponentiation, multiplex,
+    // "fsim", QFT and parity gate of QrackSimulator has a recorded version
+    // here. `time_evolve()` and the arithmetic and logic methods don't; gates
+    // applied through `simulator()`, rather than this wrapper, are not
+    // recorded, and leave the recording out of step with the simulator.
+    //
+    // Attributes:
+    //     qsim(Option<QrackSimulator>): simulator, unless only recording
+    //     qubit_count(u64): width of the recorded register
+    //     segments(Vec<QrackCircuit>): unitary segments, never empty
+    //     boundaries(Vec<MeasurementBoundary>): measurements between segments
+    qsim: Option<QrackSimulator>,
+    qubit_count: u64,
+    segments: Vec<QrackCircuit>,
+    boundaries: Vec<MeasurementBoundary>
+}
+
+impl RecordingSimulator {
+    // constructors
+    pub fn new(qsim: QrackSimulator) -> Result<Self, QrackError> {
+        // Record while simulating on "qsim"
+        let qubit_count = qsim.num_qubits()?;
+        Ok(Self{ qsim: Some(qsim), qubit_count, segments: vec![QrackCircuit::new()], boundaries: Vec::new() })
+    }
+
+    pub fn without_simulation(qubit_count: u64) -> Self {
+        // Record only; measurements have no results
+        Self{ qsim: None, qubit_count, segments: vec![QrackCircuit::new()], boundaries: Vec::new() }
+    }
+
+    pub fn simulator(&self) -> Option<&QrackSimulator> {
+        // Simulator, for queries such as probabilities; gates applied
+        // through it are not recorded
+        self.qsim.as_ref()
+    }
+
+    pub fn circuit(&self) -> &QrackCircuit {
+        // Segment being recorded
+        self.segments.last().unwrap()
+    }
+
+    pub fn segments(&self) -> &[QrackCircuit] {
+        &self.segments
+    }
+
+    pub fn boundaries(&self) -> &[MeasurementBoundary] {
+        &self.boundaries
+    }
+
+    pub fn into_parts(self) -> (Option<QrackSimulator>, Vec<QrackCircuit>, Vec<MeasurementBoundary>) {
+        // Stop recording
+        //
+        // Returns:
+        //     (simulator, segments, boundaries)
+        (self.qsim, self.segments, self.boundaries)
+    }
+
+    fn apply<S, C>(&self, sim: S, circ: C) -> Result<(), QrackError>
+        where S: FnOnce(&QrackSimulator) -> Result<(), QrackError>, C: FnOnce(&QrackCircuit)
+    {
+        // Record the gate once the simulator, if any, has applied it.
+        if let Some(ref qsim) = self.qsim {
+            sim(qsim)?;
+        }
+        circ(self.circuit());
+        Ok(())
+    }
+
+    fn apply_instructions<S>(&self, sim: S, instructions: Vec<CircuitInstruction>) -> Result<(), QrackError>
+        where S: FnOnce(&QrackSimulator) -> Result<(), QrackError>
+    {
+        // Record a gate by its decomposition, (built, and so checked, first)
+        self.apply(sim, |circ| {
+            for i in &instructions {
+                circ.append_instruction(i);
+            }
+        })
+    }
+
+    fn measure<S>(&mut self, bases: Vec<Pauli>, qubits: Vec<u64>, sim: S) -> Result<Option<u64>, QrackError>
+        where S: FnOnce(&QrackSimulator) -> Result<u64, QrackError>
+    {
+        let result = match self.qsim {
+            Some(ref qsim) => Some(sim(qsim)?),
+            None => None
+        };
+        self.boundaries.push(MeasurementBoundary{ bases, qubits, result });
+        self.segments.push(QrackCircuit::new());
+        Ok(result)
+    }
+
+    // recorded gates
+    pub fn x(&self, q: u64) -> Result<(), QrackError> {
+        // Pauli X gate
+        self.apply(|sim| sim.x(q), |circ| circ.x(q))
+    }
+
+    pub fn y(&self, q: u64) -> Result<(), QrackError> {
+        // Pauli Y gate
+        self.apply(|sim| sim.y(q), |circ| circ.y(q))
+    }
+
+    pub fn z(&self, q: u64) -> Result<(), QrackError> {
+        // Pauli Z gate
+        self.apply(|sim| sim.z(q), |circ| circ.z(q))
+    }
+
+    pub fn h(&self, q: u64) -> Result<(), QrackError> {
+        // Hadamard gate
+        self.apply(|sim| sim.h(q), |circ| circ.h(q))
+    }
+
+    pub fn s(&self, q: u64) -> Result<(), QrackError> {
+        // S gate
+        self.apply(|sim| sim.s(q), |circ| circ.s(q))
+    }
+
+    pub fn t(&self, q: u64) -> Result<(), QrackError> {
+        // T gate
+        self.apply(|sim| sim.t(q), |circ| circ.t(q))
+    }
+
+    pub fn adjs(&self, q: u64) -> Result<(), QrackError> {
+        // adjoint S gate
+        self.apply(|sim| sim.adjs(q), |circ| circ.adjs(q))
+    }
+
+    pub fn adjt(&self, q: u64) -> Result<(), QrackError> {
+        // adjoint T gate
+        self.apply(|sim| sim.adjt(q), |circ| circ.adjt(q))
+    }
+
+    pub fn u(&self, q: u64, th: f64, ph: f64, la: f64) -> Result<(), QrackError> {
+        // General unitary gate
+        self.apply(|sim| sim.u(q, th, ph, la), |circ| circ.u(q, th, ph, la))
+    }
+
+    pub fn mtrx(&self, m: &[f64;8], q: u64) -> Result<(), QrackError> {
+        // Operation from matrix
+        self.apply(|sim| sim.mtrx(m, q), |circ| circ.mtrx(m, q))
+    }
+
+    pub fn r(&self, b: Pauli, ph: f64, q: u64) -> Result<(), QrackError> {
+        // Rotation gate
+        self.apply(|sim| sim.r(b, ph, q), |circ| circ.r(b, ph, q))
+    }
+
+    pub fn exp(&self, b: Vec<Pauli>, ph: f64, q: Vec<u64>) -> Result<(), QrackError> {
+        // Arbitrary exponentiation, `e^{i*ph*[b_0 . b_1 ...]}`
+        //
+        // Raises:
+        //     LengthMismatch: "b" and "q" differ in length.
+        //     InvalidArgument: a qubit repeats.
+        let instructions = exp_instructions(&b, ph, &[], &q)?;
+        self.apply_instructions(|sim| sim.exp(b, ph, q), instructions)
+    }
+
+    pub fn mcx(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled Pauli X gate
+        self.apply(|sim| sim.mcx(c.clone(), q), |circ| circ.mcx(c.clone(), q))
+    }
+
+    pub fn mcy(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled Pauli Y gate
+        self.apply(|sim| sim.mcy(c.clone(), q), |circ| circ.mcy(c.clone(), q))
+    }
+
+    pub fn mcz(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled Pauli Z gate
+        self.apply(|sim| sim.mcz(c.clone(), q), |circ| circ.mcz(c.clone(), q))
+    }
+
+    pub fn mch(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled Hadamard gate
+        self.apply(|sim| sim.mch(c.clone(), q), |circ| circ.mch(c.clone(), q))
+    }
+
+    pub fn mcs(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled S gate
+        self.apply(|sim| sim.mcs(c.clone(), q), |circ| circ.mcs(c.clone(), q))
+    }
+
+    pub fn mct(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled T gate
+        self.apply(|sim| sim.mct(c.clone(), q), |circ| circ.mct(c.clone(), q))
+    }
+
+    pub fn mcadjs(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled adjoint S gate
+        self.apply(|sim| sim.mcadjs(c.clone(), q), |circ| circ.mcadjs(c.clone(), q))
+    }
+
+    pub fn mcadjt(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled adjoint T gate
+        self.apply(|sim| sim.mcadjt(c.clone(), q), |circ| circ.mcadjt(c.clone(), q))
+    }
+
+    pub fn mcu(&self, c: Vec<u64>, q: u64, th: f64, ph: f64, la: f64) -> Result<(), QrackError> {
+        // Multi-controlled general unitary gate
+        self.apply(|sim| sim.mcu(c.clone(), q, th, ph, la), |circ| circ.mcu(c.clone(), q, th, ph, la))
+    }
+
+    pub fn mcmtrx(&self, c: Vec<u64>, m: &[f64;8], q: u64) -> Result<(), QrackError> {
+        // Multi-controlled operation from matrix
+        self.apply(|sim| sim.mcmtrx(c.clone(), m, q), |circ| circ.mcmtrx(c.clone(), m, q))
+    }
+
+    pub fn mcr(&self, b: Pauli, ph: f64, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Multi-controlled rotation gate
+        self.apply(|sim| sim.mcr(b, ph, c.clone(), q), |circ| circ.mcr(b, ph, c.clone(), q))
+    }
+
+    pub fn macx(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Anti-controlled Pauli X gate
+        self.apply(|sim| sim.macx(c.clone(), q), |circ| circ.macx(c.clone(), q))
+    }
+
+    pub fn macy(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Anti-controlled Pauli Y gate
+        self.apply(|sim| sim.macy(c.clone(), q), |circ| circ.macy(c.clone(), q))
+    }
+
+    pub fn macz(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Anti-controlled Pauli Z gate
+        self.apply(|sim| sim.macz(c.clone(), q), |circ| circ.macz(c.clone(), q))
+    }
+
+    pub fn mach(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Anti-controlled Hadamard gate
+        self.apply(|sim| sim.mach(c.clone(), q), |circ| circ.mach(c.clone(), q))
+    }
+
+    pub fn macs(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Anti-controlled S gate
+        self.apply(|sim| sim.macs(c.clone(), q), |circ| circ.macs(c.clone(), q))
+    }
+
+    pub fn mact(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Anti-controlled T gate
+        self.apply(|sim| sim.mact(c.clone(), q), |circ| circ.mact(c.clone(), q))
+    }
+
+    pub fn macadjs(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Anti-controlled adjoint S gate
+        self.apply(|sim| sim.macadjs(c.clone(), q), |circ| circ.macadjs(c.clone(), q))
+    }
+
+    pub fn macadjt(&self, c: Vec<u64>, q: u64) -> Result<(), QrackError> {
+        // Anti-controlled adjoint T gate
+        self.apply(|sim| sim.macadjt(c.clone(), q), |circ| circ.macadjt(c.clone(), q))
+    }
+
+    pub fn macu(&self, c: Vec<u64>, q: u64, th: f64, ph: f64, la: f64) -> Result<(), QrackError> {
+        // Anti-controlled general unitary gate
+        self.apply(|sim| sim.macu(c.clone(), q, th, ph, la), |circ| circ.macu(c.clone(), q, th, ph, la))
+    }
+
+    pub fn macmtrx(&self, c: Vec<u64>, m: &[f64;8], q: u64) -> Result<(), QrackError> {
+        // Anti-controlled operation from matrix
+        self.apply(|sim| sim.macmtrx(c.clone(), m, q), |circ| circ.macmtrx(c.clone(), m, q))
+    }
+
+    pub fn ucmtrx(&self, c: Vec<u64>, m: &[f64;8], q: u64, p: u64) -> Result<(), QrackError> {
+        // Operation applied if the controls match permutation "p"
+        self.apply(|sim| sim.ucmtrx(c.clone(), m, q, p), |circ| circ.ucmtrx(c.clone(), m, q, p))
+    }
+
+    pub fn mcexp(&self, b: Vec<Pauli>, ph: f64, cs: Vec<u64>, q: Vec<u64>) -> Result<(), QrackError> {
+        // Controlled exponentiation, `e^{i*ph*[b_0 . b_1 ...]}` if every
+        // qubit of "cs" is `|1>`
+        //
+        // Raises:
+        //     LengthMismatch: "b" and "q" differ in length.
+        //     InvalidArgument: a qubit repeats.
+        let instructions = exp_instructions(&b, ph, &cs, &q)?;
+        self.apply_instructions(|sim| sim.mcexp(b, ph, cs, q), instructions)
+    }
+
+    pub fn multiplex1_mtrx(&self, c: Vec<u64>, q: u64, m: Vec<f64>) -> Result<(), QrackError> {
+        // Multiplex gate, with matrix "k" of "m" applied if the controls
+        // match permutation "k", (bit "i" the state of "c[i]")
+        //
+        // Raises:
+        //     LengthMismatch: "m" doesn't hold one matrix per permutation.
+        //     InvalidArgument: controls repeat or contain the target, or
+        //         there are 64 or more.
+        let expected = 1usize.checked_shl(c.len() as u32).and_then(|n| n.checked_mul(8))
+            .ok_or_else(|| QrackError::InvalidArgument(format!("{} controls can't be addressed by a permutation", c.len())))?;
+        if m.len() != expected {
+            return Err(QrackError::LengthMismatch{ expected, actual: m.len() });
+        }
+        let instructions = m.chunks(8).enumerate()
+            .map(|(p, mtrx)| {
+                let mut payload = [0.0; 8];
+                payload.copy_from_slice(mtrx);
+                CircuitInstruction::controlled(&c, &payload, q, p as u64)
+            })
+            .collect::<Result<Vec<CircuitInstruction>, QrackError>>()?;
+        self.apply_instructions(|sim| sim.multiplex1_mtrx(c, q, m), instructions)
+    }
+
+    pub fn mx(&self, q: Vec<u64>) -> Result<(), QrackError> {
+        // Pauli X gate on every qubit of "q"
+        self.apply(|sim| sim.mx(q.clone()), |circ| q.iter().for_each(|q| circ.x(*q)))
+    }
+
+    pub fn my(&self, q: Vec<u64>) -> Result<(), QrackError> {
+        // Pauli Y gate on every qubit of "q"
+        self.apply(|sim| sim.my(q.clone()), |circ| q.iter().for_each(|q| circ.y(*q)))
+    }
+
+    pub fn mz(&self, q: Vec<u64>) -> Result<(), QrackError> {
+        // Pauli Z gate on every qubit of "q"
+        self.apply(|sim| sim.mz(q.clone()), |circ| q.iter().for_each(|q| circ.z(*q)))
+    }
+
+    pub fn phase_parity(&self, la: f64, q: Vec<u64>) -> Result<(), QrackError> {
+        // Phase to odd parity, recorded as `e^{-i*la/2*[Z . Z ...]}`, (equal
+        // up to global phase)
+        //
+        // Raises:
+        //     InvalidArgument: a qubit repeats.
+        let instructions = exp_instructions(&vec![Pauli::PauliZ; q.len()], -la / 2.0, &[], &q)?;
+        self.apply_instructions(|sim| sim.phase_parity(la, q), instructions)
+    }
+
+    pub fn qft(&self, qs: Vec<u64>) -> Result<(), QrackError> {
+        // Quantum Fourier Transform, (without swaps), recorded gate by gate
+        // as Qrack applies it: for each qubit from the last, controlled
+        // phases from the qubits after it, then H
+        //
+        // Raises:
+        //     InvalidArgument: a qubit repeats.
+        let mut instructions = Vec::new();
+        for i in (0..qs.len()).rev() {
+            for (j, t) in qs[(i + 1)..].iter().enumerate() {
+                instructions.push(CircuitInstruction::controlled(&[qs[i]], &gate_matrix::phase(PI / 2f64.powi(j as i32 + 1)), *t, 1)?);
+            }
+            instructions.push(CircuitInstruction::new(&gate_matrix::H, qs[i]));
+        }
+        self.apply_instructions(|sim| sim.qft(qs), instructions)
+    }
+
+    pub fn iqft(&self, qs: Vec<u64>) -> Result<(), QrackError> {
+        // Inverse Quantum Fourier Transform, recorded gate by gate as Qrack
+        // applies it: for each qubit from the first, inverse controlled
+        // phases from the qubits before it, then H
+        //
+        // Raises:
+        //     InvalidArgument: a qubit repeats.
+        let mut instructions = Vec::new();
+        for i in 0..qs.len() {
+            for j in 0..i {
+                instructions.push(CircuitInstruction::controlled(&[qs[i - (j + 1)]], &gate_matrix::phase(-PI / 2f64.powi(j as i32 + 1)), qs[i], 1)?);
+            }
+            instructions.push(CircuitInstruction::new(&gate_matrix::H, qs[i]));
+        }
+        self.apply_instructions(|sim| sim.iqft(qs), instructions)
+    }
+
+    pub fn swap(&self, qi1: u64, qi2: u64) -> Result<(), QrackError> {
+        // Swap gate
+        self.apply(|sim| sim.swap(qi1, qi2), |circ| circ.swap(qi1, qi2))
+    }
+
+    pub fn cswap(&self, c: Vec<u64>, qi1: u64, qi2: u64) -> Result<(), QrackError> {
+        // Controlled swap gate
+        self.apply(|sim| sim.cswap(c.clone(), qi1, qi2), |circ| circ.cswap(c.clone(), qi1, qi2))
+    }
+
+    pub fn acswap(&self, c: Vec<u64>, qi1: u64, qi2: u64) -> Result<(), QrackError> {
+        // Anti-controlled swap gate
+        self.apply(|sim| sim.acswap(c.clone(), qi1, qi2), |circ| circ.acswap(c.clone(), qi1, qi2))
+    }
+
+    pub fn iswap(&self, qi1: u64, qi2: u64) -> Result<(), QrackError> {
+        // Swap gate with phase, recorded as `SWAP (S x S) CZ`
+        self.apply(|sim| sim.iswap(qi1, qi2), |circ| {
+            circ.mcz(vec![qi1], qi2);
+            circ.s(qi1);
+            circ.s(qi2);
+            circ.swap(qi1, qi2);
+        })
+    }
+
+    pub fn adjiswap(&self, qi1: u64, qi2: u64) -> Result<(), QrackError> {
+        // Inverse swap gate with phase, recorded as `SWAP (S^† x S^†) CZ`
+        self.apply(|sim| sim.adjiswap(qi1, qi2), |circ| {
+            circ.mcz(vec![qi1], qi2);
+            circ.adjs(qi1);
+            circ.adjs(qi2);
+            circ.swap(qi1, qi2);
+        })
+    }
+
+    pub fn fsim(&self, th: f64, ph: f64, qi1: u64, qi2: u64) -> Result<(), QrackError> {
+        // Fsim gate, recorded as a controlled "RX(2*th)" between CNOTs, (for
+        // the `|01>`, `|10>` block), then a controlled phase "ph" on `|11>`
+        //
+        // Raises:
+        //     InvalidArgument: "qi1" and "qi2" are the same qubit.
+        let cnot = CircuitInstruction::controlled(&[qi2], &gate_matrix::X, qi1, 1)?;
+        let instructions = vec![
+            cnot.clone(),
+            CircuitInstruction::controlled(&[qi1], &gate_matrix::r(Pauli::PauliX, 2.0 * th), qi2, 1)?,
+            cnot,
+            CircuitInstruction::controlled(&[qi1], &gate_matrix::phase(ph), qi2, 1)?
+        ];
+        self.apply_instructions(|sim| sim.fsim(th, ph, qi1, qi2), instructions)
+    }
+
+    // measurement boundaries
+    pub fn m(&mut self, q: u64) -> Result<Option<u64>, QrackError> {
+        // Measurement gate
+        //
+        // Returns:
+        //     Measurement result, if simulating.
+        self.measure(vec![Pauli::PauliZ], vec![q], |sim| sim.m(q))
+    }
+
+    pub fn force_m(&mut self, q: u64, r: bool) -> Result<Option<u64>, QrackError> {
+        // Force-Measurement gate
+        //
+        // Returns:
+        //     Measurement result, if simulating.
+        self.measure(vec![Pauli::PauliZ], vec![q], |sim| sim.force_m(q, r))
+    }
+
+    pub fn m_all(&mut self) -> Result<Option<u64>, QrackError> {
+        // Measure-all gate
+        //
+        // Returns:
+        //     Measurement result of all qubits, if simulating.
+        let n = self.qubit_count;
+        self.measure(vec![Pauli::PauliZ; n as usize], (0..n).collect(), |sim| sim.m_all())
+    }
+
+    pub fn measure_pauli(&mut self, b: Vec<Pauli>, q: Vec<u64>) -> Result<Option<u64>, QrackError> {
+        // Pauli Measurement gate
+        //
+        // Raises:
+        //     LengthMismatch: "b" and "q" differ in length.
+        //
+        // Returns:
+        //     Measurement result, if simulating.
+        if b.len() != q.len() {
+            return Err(QrackError::LengthMismatch{ expected: q.len(), actual: b.len() });
+        }
+        let (bases, qubits) = (b.clone(), q.clone());
+        self.measure(bases, qubits, |sim| sim.measure_pauli(b, q))
+    }
+}
+
+fn exp_instructions(b: &[Pauli], ph: f64, cs: &[u64], q: &[u64]) -> Result<Vec<CircuitInstruction>, QrackError> {
+    // `e^{i*ph*[b_0 . b_1 ...]}`, if every qubit of "cs" is `|1>`
+    //
+    // Each non-identity basis is turned to Z, a CNOT chain gathers the
+    // parity on the last qubit, and a (controlled) `diag(e^{i*ph}, e^{-i*ph})`
+    // acts there, before everything is undone. With only identities, the
+    // operator is the phase `e^{i*ph}`, applied to the controls.
+    //
+    // Raises:
+    //     LengthMismatch: "b" and "q" differ in length.
+    //     InvalidArgument: a qubit repeats.
+    if b.len() != q.len() {
+        return Err(QrackError::LengthMismatch{ expected: q.len(), actual: b.len() });
+    }
+    let (bases, qubits): (Vec<Pauli>, Vec<u64>) = b.iter().cloned().zip(q.iter().cloned())
+        .filter(|(b, _)| *b != Pauli::PauliI)
+        .unzip();
+    let mut sorted = qubits.clone();
+    sorted.sort();
+    if sorted.windows(2).any(|w| w[0] == w[1]) {
+        return Err(QrackError::InvalidArgument(format!("qubits {:?} repeat", q)));
+    }
+    let (c, s) = (ph.cos(), ph.sin());
+    let last = match qubits.last() {
+        Some(last) => *last,
+        None => return Ok(match (cs.split_last(), q.first()) {
+            (Some((t, c)), _) => vec![CircuitInstruction::controlled(c, &gate_matrix::phase(ph), *t, all_ones(c.len()))?],
+            (None, Some(t)) => vec![CircuitInstruction::new(&[c, s, 0.0, 0.0, 0.0, 0.0, c, s], *t)],
+            (None, None) => Vec::new()
+        })
+    };
+    let mut into_z = Vec::new();
+    let mut from_z = Vec::new();
+    for (b, q) in bases.iter().zip(&qubits) {
+        match b {
+            Pauli::PauliX => {
+                into_z.push(CircuitInstruction::new(&gate_matrix::H, *q));
+                from_z.push(CircuitInstruction::new(&gate_matrix::H, *q));
+            },
+            Pauli::PauliY => {
+                into_z.push(CircuitInstruction::new(&gate_matrix::ADJ_S, *q));
+                into_z.push(CircuitInstruction::new(&gate_matrix::H, *q));
+                from_z.push(CircuitInstruction::new(&gate_matrix::H, *q));
+                from_z.push(CircuitInstruction::new(&gate_matrix::S, *q));
+            },
+            _ => {}
+        }
+    }
+    let chain = qubits.windows(2)
+        .map(|w| CircuitInstruction::controlled(&[w[0]], &gate_matrix::X, w[1], 1))
+        .collect::<Result<Vec<CircuitInstruction>, QrackError>>()?;
+    let mut out = into_z;
+    out.extend(chain.iter().cloned());
+    out.push(CircuitInstruction::controlled(cs, &[c, s, 0.0, 0.0, 0.0, 0.0, c, -s], last, all_ones(cs.len()))?);
+    out.extend(chain.into_iter().rev());
+    out.extend(from_z);
+    Ok(out)
+}
@@ -0,0 +1,284 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+// Routing of circuits onto a CouplingMap, with the SABRE heuristic.
+//
+// Gates run once their qubits are coupled; otherwise, the swap that most
+// shortens the distances of the front layer, (plus, at half weight, of the
+// next gates), is inserted, with a decay that discourages thrashing the same
+// qubits. The initial layout is found by routing the circuit forward and then
+// backward, starting from the trivial layout. (See Li, Ding and Xie,
+// https://arxiv.org/abs/1809.02573.)
+
+use std::collections::{BTreeSet, VecDeque};
+
+use num_complex::Complex64;
+use circuit_instruction::CircuitInstruction;
+use coupling_map::CouplingMap;
+use gate_matrix;
+use qrack_circuit::QrackCircuit;
+use qrack_error::QrackError;
+use qrack_simulator::QrackSimulator;
+use transpile::{to_circuit, RandomStates};
+
+// Next gates that weigh on the choice of swap, and their weight
+const EXTENDED_SET_SIZE: usize = 20;
+const EXTENDED_SET_WEIGHT: f64 = 0.5;
+// Decay added to swapped qubits, and swaps between decay resets
+const DECAY_STEP: f64 = 0.001;
+const DECAY_RESET: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutedCircuit {
+    // Circuit on physical qubits, with every two-qubit gate on a coupled pair
+    //
+    // Logical qubits past the original circuit's width are idle, and only
+    // complete the permutations.
+    //
+    // Attributes:
+    //     instructions(Vec<CircuitInstruction>): routed gates, with each
+    //         inserted swap as three CNOTs, (as Qrack records swaps)
+    //     initial_layout(Vec<u64>): physical qubit of each logical qubit, at the start
+    //     final_layout(Vec<u64>): physical qubit of each logical qubit, at the end
+    //     swap_count(usize): inserted swaps
+    pub instructions: Vec<CircuitInstruction>,
+    pub initial_layout: Vec<u64>,
+    pub final_layout: Vec<u64>,
+    pub swap_count: usize
+}
+
+impl RoutedCircuit {
+    pub fn circuit(&self) -> QrackCircuit {
+        // Routed gates as a runnable QrackCircuit, (recorded as given)
+        to_circuit(&self.instructions)
+    }
+}
+
+pub fn route(circuit: &QrackCircuit, map: &CouplingMap) -> Result<RoutedCircuit, QrackError> {
+    // Route a circuit onto "map"
+    //
+    // Gates may act on at most two qubits; see `transpile::transpile()`.
+    //
+    // Raises:
+    //     Io: the circuit couldn't be read back from Qrack.
+    //     InvalidArgument: a gate acts on more than two qubits, the circuit is
+    //         wider than "map", or "map" isn't connected.
+    route_instructions(circuit.get_qubit_count(), &circuit.instructions()?, map)
+}
+
+pub fn route_instructions(qubit_count: u64, instructions: &[CircuitInstruction], map: &CouplingMap) -> Result<RoutedCircuit, QrackError> {
+    // Route instructions on "qubit_count" logical qubits onto "map"
+    //
+    // Raises:
+    //     InvalidArgument: a gate acts on more than two qubits, the circuit is
+    //         wider than "map", or "map" isn't connected.
+    let width = instructions.iter().flat_map(|i| i.qubits()).map(|q| q + 1).max().unwrap_or(0).max(qubit_count);
+    if width > map.qubit_count() {
+        return Err(QrackError::InvalidArgument(format!("circuit needs {} qubits, but the coupling map has {}", width, map.qubit_count())));
+    }
+    if !map.is_connected() {
+        return Err(QrackError::InvalidArgument("coupling map is not connected".to_string()));
+    }
+    if let Some(i) = instructions.iter().find(|i| i.controls.len() > 1) {
+        return Err(QrackError::InvalidArgument(format!("gate on {} qubits can't be routed; transpile it first", i.controls.len() + 1)));
+    }
+
+    let router = Router::new(map);
+    let gates: Vec<Vec<u64>> = instructions.iter().map(|i| i.qubits()).collect();
+    let reversed: Vec<Vec<u64>> = gates.iter().rev().cloned().collect();
+    let trivial: Vec<u64> = (0..map.qubit_count()).collect();
+    let (_, forward) = router.run(&gates, trivial);
+    let (_, initial_layout) = router.run(&reversed, forward);
+    let (steps, final_layout) = router.run(&gates, initial_layout.clone());
+
+    let mut out = Vec::new();
+    let mut swap_count = 0;
+    for step in steps {
+        match step {
+            Step::Gate(g, layout) => {
+                let i = &instructions[g];
+                out.push(CircuitInstruction{
+                    target: layout[i.target as usize],
+                    controls: i.controls.iter().map(|c| layout[*c as usize]).collect(),
+                    payloads: i.payloads.clone()
+                });
+            },
+            Step::Swap(a, b) => {
+                for (c, t) in [(b, a), (a, b), (b, a)].iter() {
+                    out.push(CircuitInstruction::controlled(&[*c], &gate_matrix::X, *t, 1)?);
+                }
+                swap_count += 1;
+            }
+        }
+    }
+    Ok(RoutedCircuit{ instructions: out, initial_layout, final_layout, swap_count })
+}
+
+pub fn verify(original: &QrackCircuit, routed: &RoutedCircuit, trials: usize, seed: u64) -> Result<f64, QrackError> {
+    // Compare a routed circuit to its original, on QrackSimulator
+    //
+    // Each trial prepares the same random entangled state on the original's
+    // qubits, and on their initial physical qubits, runs both circuits, and
+    // takes the fidelity of the results, after relabeling by the final layout.
+    //
+    // Args:
+    //     original(&QrackCircuit): circuit before routing
+    //     routed(&RoutedCircuit): output of `route()`
+    //     trials(usize): number of random input states
+    //     seed(u64): seed of the random input states
+    //
+    // Raises:
+    //     Native: Qrack reported an error code.
+    //
+    // Returns:
+    //     lowest fidelity over the trials, (1 if they agree up to global phase)
+    let qubits = routed.final_layout.len() as u64;
+    let logical: Vec<u64> = (0..original.get_qubit_count()).collect();
+    let physical: Vec<u64> = logical.iter().map(|q| routed.initial_layout[*q as usize]).collect();
+    let candidate = routed.circuit();
+    let mut states = RandomStates::new(seed);
+    let mut worst: f64 = 1.0;
+    for _ in 0..trials {
+        let (a, b) = (QrackSimulator::new(qubits)?, QrackSimulator::new(qubits)?);
+        states.clone().prepare(&a, &logical)?;
+        states.prepare(&b, &physical)?;
+        original.run(&a)?;
+        candidate.run(&b)?;
+        let (ka, kb) = (a.out_ket::<f64>()?, b.out_ket::<f64>()?);
+        let overlap: Complex64 = ka.iter().enumerate()
+            .map(|(i, x)| {
+                let j = routed.final_layout.iter().enumerate()
+                    .filter(|(l, _)| (i >> l) & 1 == 1)
+                    .fold(0usize, |j, (_, p)| j | (1 << p));
+                x.conj() * kb[j]
+            })
+            .sum();
+        worst = worst.min(overlap.norm_sqr());
+    }
+    Ok(worst)
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    // Gate index, with the layout it runs in, or a swap of physical qubits
+    Gate(usize, Vec<u64>),
+    Swap(u64, u64)
+}
+
+struct Router<'a> {
+    map: &'a CouplingMap,
+    dist: Vec<Vec<u64>>
+}
+
+impl<'a> Router<'a> {
+    fn new(map: &'a CouplingMap) -> Self {
+        let dist = map.distances().into_iter()
+            .map(|row| row.into_iter().map(|d| d.unwrap_or(u64::MAX)).collect())
+            .collect();
+        Router{ map, dist }
+    }
+
+    fn distance(&self, layout: &[u64], gate: &[u64]) -> u64 {
+        if gate.len() < 2 {
+            0
+        } else {
+            self.dist[layout[gate[0] as usize] as usize][layout[gate[1] as usize] as usize]
+        }
+    }
+
+    fn run(&self, gates: &[Vec<u64>], mut layout: Vec<u64>) -> (Vec<Step>, Vec<u64>) {
+        // Route "gates", (logical qubit lists), from "layout", returning the
+        // steps taken and the final layout
+        let n = layout.len();
+        let mut physical = vec![0u64; n];
+        for (l, p) in layout.iter().enumerate() {
+            physical[*p as usize] = l as u64;
+        }
+        let mut queues: Vec<VecDeque<usize>> = vec![VecDeque::new(); n];
+        for (g, qubits) in gates.iter().enumerate() {
+            for q in qubits {
+                queues[*q as usize].push_back(g);
+            }
+        }
+        let mut done = vec![false; gates.len()];
+        let mut next_pending = 0;
+        let mut decay = vec![1.0f64; n];
+        let mut swaps_since_gate = 0;
+        let mut steps = Vec::new();
+        loop {
+            // Run every gate at the front that the layout allows.
+            let mut blocked: Vec<usize>;
+            loop {
+                let front: BTreeSet<usize> = queues.iter()
+                    .filter_map(|q| q.front().cloned())
+                    .filter(|g| gates[*g].iter().all(|q| queues[*q as usize].front() == Some(g)))
+                    .collect();
+                let (ready, rest): (Vec<usize>, Vec<usize>) = front.into_iter().partition(|g| self.distance(&layout, &gates[*g]) <= 1);
+                blocked = rest;
+                if ready.is_empty() {
+                    break;
+                }
+                for g in ready {
+                    for q in &gates[g] {
+                        queues[*q as usize].pop_front();
+                    }
+                    done[g] = true;
+                    steps.push(Step::Gate(g, layout.clone()));
+                }
+                decay.iter_mut().for_each(|d| *d = 1.0);
+                swaps_since_gate = 0;
+            }
+            if blocked.is_empty() {
+                break;
+            }
+            while next_pending < gates.len() && done[next_pending] {
+                next_pending += 1;
+            }
+            let extended: Vec<usize> = (next_pending..gates.len())
+                .filter(|g| !done[*g] && gates[*g].len() == 2 && !blocked.contains(g))
+                .take(EXTENDED_SET_SIZE)
+                .collect();
+
+            let (p1, p2) = if swaps_since_gate > 2 * n {
+                // No progress; walk the first blocked gate's qubits together.
+                let (a, b) = (layout[gates[blocked[0]][0] as usize], layout[gates[blocked[0]][1] as usize]);
+                let step = self.map.neighbors(a).into_iter().find(|nb| self.dist[*nb as usize][b as usize] < self.dist[a as usize][b as usize]).unwrap();
+                (a, step)
+            } else {
+                let candidates: BTreeSet<(u64, u64)> = blocked.iter()
+                    .flat_map(|g| gates[*g].iter().map(|q| layout[*q as usize]))
+                    .flat_map(|p| self.map.neighbors(p).into_iter().map(move |nb| (p.min(nb), p.max(nb))))
+                    .collect();
+                let mut best = None;
+                for (a, b) in candidates {
+                    let (la, lb) = (physical[a as usize] as usize, physical[b as usize] as usize);
+                    layout.swap(la, lb);
+                    let cost = |set: &[usize]| if set.is_empty() {
+                        0.0
+                    } else {
+                        set.iter().map(|g| self.distance(&layout, &gates[*g]) as f64).sum::<f64>() / set.len() as f64
+                    };
+                    let score = decay[a as usize].max(decay[b as usize]) * (cost(&blocked) + EXTENDED_SET_WEIGHT * cost(&extended));
+                    layout.swap(la, lb);
+                    if best.is_none_or(|(s, _)| score < s) {
+                        best = Some((score, (a, b)));
+                    }
+                }
+                best.unwrap().1
+            };
+            let (l1, l2) = (physical[p1 as usize], physical[p2 as usize]);
+            layout.swap(l1 as usize, l2 as usize);
+            physical.swap(p1 as usize, p2 as usize);
+            steps.push(Step::Swap(p1, p2));
+            decay[p1 as usize] += DECAY_STEP;
+            decay[p2 as usize] += DECAY_STEP;
+            swaps_since_gate += 1;
+            if swaps_since_gate % DECAY_RESET == 0 {
+                decay.iter_mut().for_each(|d| *d = 1.0);
+            }
+        }
+        (steps, layout)
+    }
+}
@@ -0,0 +1,202 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+use qrack_error::QrackError;
+use qrack_simulator::QrackSimulator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulatorConfig {
+    // Layer stack of a QrackSimulator
+    //
+    // Each flag maps, in order, to an argument of
+    // `qrack_system::init_count_type()`.
+    //
+    // Attributes:
+    //     tensor_network(bool): use the tensor network layer ("tn")
+    //     schmidt_decompose_multi(bool): distribute Schmidt-decomposed subsystems
+    //         over multiple devices ("md")
+    //     schmidt_decompose(bool): use Schmidt decomposition (QUnit) ("sd")
+    //     stabilizer_hybrid(bool): use the stabilizer/near-Clifford hybrid layer ("sh")
+    //     binary_decision_tree(bool): use the binary decision tree layer ("bdt")
+    //     paged(bool): page the state vector over devices ("pg")
+    //     zx_fusion(bool): use ZX-calculus gate fusion ("zxf")
+    //     cpu_gpu_hybrid(bool): switch between CPU and GPU by width ("hy")
+    //     opencl(bool): use OpenCL ("oc")
+    //     host_pointer(bool): allocate OpenCL buffers in host memory ("dm")
+    pub tensor_network: bool,
+    pub schmidt_decompose_multi: bool,
+    pub schmidt_decompose: bool,
+    pub stabilizer_hybrid: bool,
+    pub binary_decision_tree: bool,
+    pub paged: bool,
+    pub zx_fusion: bool,
+    pub cpu_gpu_hybrid: bool,
+    pub opencl: bool,
+    pub host_pointer: bool
+}
+
+impl Default for SimulatorConfig {
+    fn default() -> Self {
+        // Same defaults as PyQrack's QrackSimulator constructor
+        Self{
+            tensor_network: true,
+            schmidt_decompose_multi: false,
+            schmidt_decompose: true,
+            stabilizer_hybrid: true,
+            binary_decision_tree: false,
+            paged: true,
+            zx_fusion: false,
+            cpu_gpu_hybrid: true,
+            opencl: true,
+            host_pointer: false
+        }
+    }
+}
+
+impl SimulatorConfig {
+    pub fn stabilizer() -> Self {
+        // Layer stack that can save and load stabilizer tableaux
+        Self{
+            tensor_network: false,
+            schmidt_decompose_multi: false,
+            schmidt_decompose: false,
+            stabilizer_hybrid: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_stabilizer_representable(&self) -> bool {
+        // Whether the top layer is the stabilizer hybrid, (required by the
+        // stabilizer file functions)
+        self.stabilizer_hybrid
+            && !self.tensor_network
+            && !self.schmidt_decompose
+            && !self.schmidt_decompose_multi
+    }
+
+    pub fn effective(&self) -> Self {
+        // Flags as Qrack applies them
+        //
+        // Qrack ignores "cpu_gpu_hybrid" and "host_pointer" without "opencl",
+        // and "schmidt_decompose_multi" without "schmidt_decompose", so these
+        // are cleared rather than rejected.
+        Self{
+            schmidt_decompose_multi: self.schmidt_decompose_multi && self.schmidt_decompose,
+            cpu_gpu_hybrid: self.cpu_gpu_hybrid && self.opencl,
+            host_pointer: self.host_pointer && self.opencl,
+            ..*self
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    // Stabilizer file I/O needs the stabilizer hybrid as top layer.
+    NotStabilizer
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigError::NotStabilizer =>
+                write!(f, "stabilizer file I/O requires stabilizer_hybrid without tensor_network or schmidt_decompose")
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimulatorBuilder {
+    // Builder for QrackSimulator
+    //
+    // Start from `QrackSimulator::builder()`, which uses the default
+    // `SimulatorConfig` and 0 qubits.
+    //
+    // Attributes:
+    //     qubits(u64): qubit count
+    //     config(SimulatorConfig): layer stack
+    qubits: u64,
+    config: SimulatorConfig
+}
+
+impl SimulatorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn qubits(mut self, qubit_count: u64) -> Self {
+        self.qubits = qubit_count;
+        self
+    }
+
+    pub fn config(mut self, config: SimulatorConfig) -> Self {
+        // Replace all layer flags at once
+        self.config = config;
+        self
+    }
+
+    pub fn tensor_network(mut self, on: bool) -> Self {
+        self.config.tensor_network = on;
+        self
+    }
+
+    pub fn schmidt_decompose_multi(mut self, on: bool) -> Self {
+        self.config.schmidt_decompose_multi = on;
+        self
+    }
+
+    pub fn schmidt_decompose(mut self, on: bool) -> Self {
+        self.config.schmidt_decompose = on;
+        self
+    }
+
+    pub fn stabilizer_hybrid(mut self, on: bool) -> Self {
+        self.config.stabilizer_hybrid = on;
+        self
+    }
+
+    pub fn binary_decision_tree(mut self, on: bool) -> Self {
+        self.config.binary_decision_tree = on;
+        self
+    }
+
+    pub fn paged(mut self, on: bool) -> Self {
+        self.config.paged = on;
+        self
+    }
+
+    pub fn zx_fusion(mut self, on: bool) -> Self {
+        self.config.zx_fusion = on;
+        self
+    }
+
+    pub fn cpu_gpu_hybrid(mut self, on: bool) -> Self {
+        self.config.cpu_gpu_hybrid = on;
+        self
+    }
+
+    pub fn opencl(mut self, on: bool) -> Self {
+        self.config.opencl = on;
+        self
+    }
+
+    pub fn host_pointer(mut self, on: bool) -> Self {
+        self.config.host_pointer = on;
+        self
+    }
+
+    pub fn build(self) -> Result<QrackSimulator, QrackError> {
+        // Construct the simulator
+        //
+        // Layer flags are passed through to `init_count_type()`, after
+        // `SimulatorConfig::effective()` clears the ones Qrack ignores.
+        //
+        // Raises:
+        //     Native: Qrack reported an error code.
+
+        QrackSimulator::from_config(self.qubits, self.config.effective())
+    }
+}
@@ -0,0 +1,430 @@
+// (C) Daniel Strano and the Qrack contributors 2017-2023. All rights reserved.
+//
+// Use of this source code is governed by an MIT-style license that can be
+// found in the LICENSE file or at https://opensource.org/licenses/MIT.
+
+// Rewriting of circuits into a native gate basis.
+//
+// Every payload of a uniformly controlled gate becomes a multi-controlled
+// gate, (anti-controls are conjugated by X), and then:
+//     - 1 control: the "ABC" decomposition, with a phase on the control,
+//     - 2 controls of X: the standard 6-CNOT Toffoli,
+//     - "k" > 2 controls, with `k - 1` clean ancillas: an AND chain of
+//       Toffolis into them, (`12(k - 1) + 2` CNOTs),
+//     - with fewer, (but some), clean ancillas: X by Barenco et al.
+//       Lemmas 7.2 and 7.3, borrowing the ancillas as dirty ones, and other
+//       gates controlled by one ancilla holding the AND of the controls,
+//       computed that way, (O(k) CNOTs),
+//     - without ancillas: Lemma 7.5 recursion, with each `C^{k-1}X` by
+//       Lemma 7.3, borrowing the target, (O(k^2) CNOTs).
+// (See https://arxiv.org/abs/quant-ph/9503016.) Adjacent single-qubit gates
+// are then fused, and synthesized from their ZYZ Euler angles.
+//
+// `transpile()` records the result into a QrackCircuit with collapse off,
+// so Qrack keeps the gates as rewritten; `transpile_instructions()` works
+// on the instruction list instead.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::f64::consts::PI;
+
+use num_complex::Complex64;
+use circuit_instruction::CircuitInstruction;
+use gate_matrix;
+use pauli::Pauli;
+use qrack_circuit::QrackCircuit;
+use qrack_error::QrackError;
+use qrack_simulator::QrackSimulator;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Basis {
+    // CNOT and general single-qubit "U(theta, phi, lambda)"
+    #[default]
+    CxU,
+    // CZ, and single-qubit Pauli X and Z rotations
+    CzRxRz
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TranspileOptions {
+    // Attributes:
+    //     basis(Basis): target gate set
+    //     ancillas(Vec<u64>): qubits the circuit doesn't use, which start and
+    //         are returned in `|0>`, to shorten multi-controlled gates
+    pub basis: Basis,
+    pub ancillas: Vec<u64>
+}
+
+pub fn transpile(circuit: &QrackCircuit, options: &TranspileOptions) -> Result<QrackCircuit, QrackError> {
+    // Rewrite the (optimized) circuit into "options.basis"
+    //
+    // Raises:
+    //     Io: the circuit couldn't be read back from Qrack.
+    //     InvalidArgument: an ancilla repeats or is used by the circuit.
+    Ok(to_circuit(&transpile_instructions(&circuit.instructions()?, options)?))
+}
+
+pub fn transpile_instructions(instructions: &[CircuitInstruction], options: &TranspileOptions) -> Result<Vec<CircuitInstruction>, QrackError> {
+    // Rewrite instructions into "options.basis"
+    //
+    // The result equals the input up to global phase.
+    //
+    // Raises:
+    //     InvalidArgument: an ancilla repeats or is used by the instructions.
+    let ancillas: BTreeSet<u64> = options.ancillas.iter().cloned().collect();
+    if ancillas.len() != options.ancillas.len() {
+        return Err(QrackError::InvalidArgument("ancillas repeat".to_string()));
+    }
+    if let Some(q) = instructions.iter().flat_map(|i| i.qubits()).find(|q| ancillas.contains(q)) {
+        return Err(QrackError::InvalidArgument(format!("ancilla {} is used by the circuit", q)));
+    }
+    let mut d = Decomposer{ ops: Vec::new(), ancillas: options.ancillas.clone() };
+    for i in instructions {
+        for (p, m) in &i.payloads {
+            let anti: Vec<u64> = i.controls.iter().enumerate().filter(|(j, _)| (p >> j) & 1 == 0).map(|(_, c)| *c).collect();
+            for c in &anti {
+                d.one(*c, to_complex(&gate_matrix::X));
+            }
+            d.mcu(&i.controls, to_complex(m), i.target);
+            for c in &anti {
+                d.one(*c, to_complex(&gate_matrix::X));
+            }
+        }
+    }
+    Ok(emit(d.ops, options.basis))
+}
+
+pub fn to_circuit(instructions: &[CircuitInstruction]) -> QrackCircuit {
+    // Record instructions into a new QrackCircuit, (with collapse off, so
+    // they are kept as given)
+    let circ = QrackCircuit::new();
+    for i in instructions {
+        circ.append_instruction(i);
+    }
+    circ
+}
+
+pub fn verify(original: &QrackCircuit, transpiled: &QrackCircuit, trials: usize, seed: u64) -> Result<f64, QrackError> {
+    // Compare a transpiled circuit to its original, on QrackSimulator
+    //
+    // Each trial prepares a random entangled state on the original's qubits,
+    // (ancillas stay `|0>`), runs both circuits on copies of it, and takes the
+    // fidelity of the results.
+    //
+    // Args:
+    //     original(&QrackCircuit): circuit before transpiling
+    //     transpiled(&QrackCircuit): output of `transpile()`
+    //     trials(usize): number of random input states
+    //     seed(u64): seed of the random input states
+    //
+    // Raises:
+    //     Native: Qrack reported an error code.
+    //
+    // Returns:
+    //     lowest fidelity over the trials, (1 if they agree up to global phase)
+    let width = original.get_qubit_count();
+    let qubits = transpiled.get_qubit_count().max(width);
+    let mut states = RandomStates::new(seed);
+    let logical: Vec<u64> = (0..width).collect();
+    let mut worst: f64 = 1.0;
+    for _ in 0..trials {
+        let a = QrackSimulator::new(qubits)?;
+        states.prepare(&a, &logical)?;
+        let b = a.clone();
+        original.run(&a)?;
+        transpiled.run(&b)?;
+        let (ka, kb) = (a.out_ket::<f64>()?, b.out_ket::<f64>()?);
+        let overlap: Complex64 = ka.iter().zip(kb.iter()).map(|(x, y)| x.conj() * y).sum();
+        worst = worst.min(overlap.norm_sqr());
+    }
+    Ok(worst)
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RandomStates(u64);
+
+impl RandomStates {
+    // Seeded source of random entangled input states, for verification
+
+    pub(crate) fn new(seed: u64) -> Self {
+        RandomStates(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn angle(&mut self) -> f64 {
+        // xorshift64*
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64 * 2.0 * PI
+    }
+
+    pub(crate) fn prepare(&mut self, qsim: &QrackSimulator, qubits: &[u64]) -> Result<(), QrackError> {
+        // Two layers of random "U" gates and staggered CNOTs, on "qubits" in
+        // order, (other qubits are left alone)
+        for layer in 0..2 {
+            for q in qubits {
+                qsim.u(*q, self.angle(), self.angle(), self.angle())?;
+            }
+            for pair in qubits.get(layer..).unwrap_or(&[]).chunks(2).filter(|p| p.len() == 2) {
+                qsim.mcx(vec![pair[0]], pair[1])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+type Matrix = [Complex64; 4];
+
+#[derive(Debug, Clone)]
+enum Native {
+    One(u64, Matrix),
+    Cx(u64, u64)
+}
+
+struct Decomposer {
+    ops: Vec<Native>,
+    ancillas: Vec<u64>
+}
+
+impl Decomposer {
+    fn one(&mut self, q: u64, m: Matrix) {
+        self.ops.push(Native::One(q, m));
+    }
+
+    fn cx(&mut self, c: u64, t: u64) {
+        self.ops.push(Native::Cx(c, t));
+    }
+
+    fn mcu(&mut self, c: &[u64], m: Matrix, t: u64) {
+        // Exact multi-controlled "m", (all controls `|1>`)
+        let is_x = approx_eq(&m, &to_complex(&gate_matrix::X));
+        match c.len() {
+            0 => self.one(t, m),
+            1 => self.cu(c[0], m, t),
+            2 if is_x => self.toffoli(c[0], c[1], t),
+            k if self.ancillas.len() + 1 >= k => {
+                // AND the controls into a chain of clean ancillas.
+                let a: Vec<u64> = self.ancillas[..(k - 1)].to_vec();
+                let mut chain = vec![(c[0], c[1], a[0])];
+                for j in 2..k {
+                    chain.push((a[j - 2], c[j], a[j - 1]));
+                }
+                for (x, y, z) in &chain {
+                    self.toffoli(*x, *y, *z);
+                }
+                self.cu(a[k - 2], m, t);
+                for (x, y, z) in chain.iter().rev() {
+                    self.toffoli(*x, *y, *z);
+                }
+            },
+            _ if is_x && !self.ancillas.is_empty() => {
+                let dirty = self.ancillas.clone();
+                self.mcx(c, t, &dirty);
+            },
+            _ if !self.ancillas.is_empty() => {
+                // AND the controls into one clean ancilla, borrowing the
+                // other ancillas and the target.
+                let a = self.ancillas[0];
+                let mut dirty = self.ancillas[1..].to_vec();
+                dirty.push(t);
+                self.mcx(c, a, &dirty);
+                self.cu(a, m, t);
+                self.mcx(c, a, &dirty);
+            },
+            k => {
+                // Lemma 7.5: `C^k(U) = C(V) C^{k-1}X C(V^†) C^{k-1}X C^{k-1}(V)`,
+                // with `V^2 = U`, and the last control as the pivot. The
+                // `C^{k-1}X` borrow the target.
+                let v = sqrt(&m);
+                let (rest, last) = (&c[..(k - 1)], c[k - 1]);
+                self.cu(last, v, t);
+                self.mcx(rest, last, &[t]);
+                self.cu(last, adjoint(&v), t);
+                self.mcx(rest, last, &[t]);
+                self.mcu(rest, v, t);
+            }
+        }
+    }
+
+    fn mcx(&mut self, c: &[u64], t: u64, dirty: &[u64]) {
+        // Multi-controlled X, borrowing "dirty" qubits in any state, (and
+        // leaving them unchanged); more than 2 controls need at least one
+        let m = c.len();
+        match m {
+            0 => self.one(t, to_complex(&gate_matrix::X)),
+            1 => self.cx(c[0], t),
+            2 => self.toffoli(c[0], c[1], t),
+            _ if dirty.len() + 2 >= m => {
+                // Lemma 7.2: a ladder of Toffolis through `m - 2` borrowed
+                // qubits, down and up twice, then once more to restore them
+                let d = &dirty[..(m - 2)];
+                let top = (c[m - 1], d[m - 3], t);
+                let down: Vec<(u64, u64, u64)> = (1..(m - 2)).rev().map(|i| (c[i + 1], d[i - 1], d[i])).collect();
+                let bottom = (c[0], c[1], d[0]);
+                let mut seq = vec![top];
+                seq.extend(down.iter().cloned());
+                seq.push(bottom);
+                seq.extend(down.iter().rev().cloned());
+                seq.push(top);
+                seq.extend(down.iter().cloned());
+                seq.push(bottom);
+                seq.extend(down.iter().rev().cloned());
+                for (x, y, z) in seq {
+                    self.toffoli(x, y, z);
+                }
+            },
+            _ => {
+                // Lemma 7.3: split the controls in halves, each borrowing the
+                // other half, with one borrowed qubit "a" between them
+                let a = dirty[0];
+                let (c1, c2) = c.split_at(m.div_ceil(2));
+                let mut c2a = c2.to_vec();
+                c2a.push(a);
+                let mut d1 = c2.to_vec();
+                d1.push(t);
+                for _ in 0..2 {
+                    self.mcx(c1, a, &d1);
+                    self.mcx(&c2a, t, c1);
+                }
+            }
+        }
+    }
+
+    fn cu(&mut self, c: u64, m: Matrix, t: u64) {
+        // Controlled `e^{i*gamma} U(theta, phi, lambda)`, exactly, as
+        // `P(gamma + (lambda + phi)/2)` on the control, and `A X B X C` on the target
+        let (th, ph, la, gamma) = gate_matrix::zyz(&from_complex(&m));
+        self.one(c, to_complex(&gate_matrix::phase(gamma + (la + ph) / 2.0)));
+        self.one(t, to_complex(&gate_matrix::phase((la - ph) / 2.0)));
+        self.cx(c, t);
+        self.one(t, to_complex(&gate_matrix::u(-th / 2.0, 0.0, -(ph + la) / 2.0)));
+        self.cx(c, t);
+        self.one(t, to_complex(&gate_matrix::u(th / 2.0, ph, 0.0)));
+    }
+
+    fn toffoli(&mut self, a: u64, b: u64, t: u64) {
+        let (h, tg, tdg) = (to_complex(&gate_matrix::H), to_complex(&gate_matrix::T), to_complex(&gate_matrix::ADJ_T));
+        self.one(t, h);
+        self.cx(b, t);
+        self.one(t, tdg);
+        self.cx(a, t);
+        self.one(t, tg);
+        self.cx(b, t);
+        self.one(t, tdg);
+        self.cx(a, t);
+        self.one(b, tg);
+        self.one(t, tg);
+        self.one(t, h);
+        self.cx(a, b);
+        self.one(a, tg);
+        self.one(b, tdg);
+        self.cx(a, b);
+    }
+}
+
+fn emit(ops: Vec<Native>, basis: Basis) -> Vec<CircuitInstruction> {
+    // Fuse runs of single-qubit gates, and write out the basis gates
+    let h = to_complex(&gate_matrix::H);
+    let mut pending: BTreeMap<u64, Matrix> = BTreeMap::new();
+    let mut out = Vec::new();
+    for op in ops {
+        match op {
+            Native::One(q, m) => {
+                let prev = pending.remove(&q).unwrap_or_else(|| to_complex(&gate_matrix::I));
+                pending.insert(q, mul(&m, &prev));
+            },
+            Native::Cx(c, t) => {
+                flush(&mut pending, c, basis, &mut out);
+                match basis {
+                    Basis::CxU => {
+                        flush(&mut pending, t, basis, &mut out);
+                        out.push(CircuitInstruction::controlled(&[c], &gate_matrix::X, t, 1).unwrap());
+                    },
+                    Basis::CzRxRz => {
+                        // `CX = H CZ H`, on the target
+                        let prev = pending.remove(&t).unwrap_or_else(|| to_complex(&gate_matrix::I));
+                        pending.insert(t, mul(&h, &prev));
+                        flush(&mut pending, t, basis, &mut out);
+                        out.push(CircuitInstruction::controlled(&[c], &gate_matrix::Z, t, 1).unwrap());
+                        pending.insert(t, h);
+                    }
+                }
+            }
+        }
+    }
+    let rest: Vec<u64> = pending.keys().cloned().collect();
+    for q in rest {
+        flush(&mut pending, q, basis, &mut out);
+    }
+    out
+}
+
+fn flush(pending: &mut BTreeMap<u64, Matrix>, q: u64, basis: Basis, out: &mut Vec<CircuitInstruction>) {
+    // Synthesize the fused gate on "q", up to global phase
+    let m = match pending.remove(&q) {
+        Some(m) => from_complex(&m),
+        None => return
+    };
+    let (th, ph, la, _) = gate_matrix::zyz(&m);
+    let zero = |x: f64| wrap(x).abs() < 1e-10;
+    match basis {
+        Basis::CxU => {
+            if !(zero(th) && zero(ph + la)) {
+                out.push(CircuitInstruction::new(&gate_matrix::u(th, ph, la), q));
+            }
+        },
+        Basis::CzRxRz => {
+            // `U(theta, phi, lambda) = RZ(phi + pi/2) RX(theta) RZ(lambda - pi/2)`,
+            // up to global phase
+            let rotations = if zero(th) {
+                vec![(Pauli::PauliZ, ph + la)]
+            } else {
+                vec![(Pauli::PauliZ, la - PI / 2.0), (Pauli::PauliX, th), (Pauli::PauliZ, ph + PI / 2.0)]
+            };
+            for (b, angle) in rotations {
+                if !zero(angle) {
+                    out.push(CircuitInstruction::new(&gate_matrix::r(b, wrap(angle)), q));
+                }
+            }
+        }
+    }
+}
+
+fn wrap(x: f64) -> f64 {
+    // Angle in [-pi, pi)
+    x - 2.0 * PI * ((x + PI) / (2.0 * PI)).floor()
+}
+
+fn to_complex(m: &[f64;8]) -> Matrix {
+    [Complex64::new(m[0], m[1]), Complex64::new(m[2], m[3]), Complex64::new(m[4], m[5]), Complex64::new(m[6], m[7])]
+}
+
+fn from_complex(m: &Matrix) -> [f64;8] {
+    [m[0].re, m[0].im, m[1].re, m[1].im, m[2].re, m[2].im, m[3].re, m[3].im]
+}
+
+fn approx_eq(a: &Matrix, b: &Matrix) -> bool {
+    gate_matrix::approx_eq(&from_complex(a), &from_complex(b), gate_matrix::TOLERANCE)
+}
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    [
+        a[0] * b[0] + a[1] * b[2], a[0] * b[1] + a[1] * b[3],
+        a[2] * b[0] + a[3] * b[2], a[2] * b[1] + a[3] * b[3]
+    ]
+}
+
+fn adjoint(m: &Matrix) -> Matrix {
+    [m[0].conj(), m[2].conj(), m[1].conj(), m[3].conj()]
+}
+
+fn sqrt(m: &Matrix) -> Matrix {
+    // Principal-ish square root of a 2x2 unitary:
+    // `sqrt(M) = (M + s I) / sqrt(tr(M) + 2 s)`, with `s = ±sqrt(det(M))`
+    // chosen to keep the denominator away from 0
+    let det = m[0] * m[3] - m[1] * m[2];
+    let tr = m[0] + m[3];
+    let s = det.sqrt();
+    let s = if (tr + 2.0 * s).norm() >= (tr - 2.0 * s).norm() { s } else { -s };
+    let d = (tr + 2.0 * s).sqrt();
+    [(m[0] + s) / d, m[1] / d, m[2] / d, (m[3] + s) / d]
+}